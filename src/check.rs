@@ -0,0 +1,211 @@
+//! Has the `check` command, which looks for problems in a track list
+//! without having to wait for the player to stumble upon them.
+//!
+//! Every track is requested with a `HEAD` request, or a tiny range request if the
+//! server doesn't support those. Local tracks are checked to make sure they exist,
+//! and the start of each one is read to make sure that it's actually audio.
+
+use std::{collections::HashMap, io::Cursor, time::Duration};
+
+use bytes::Bytes;
+use eyre::bail;
+use futures::{stream, StreamExt as _};
+use reqwest::{header, Client, Response, StatusCode};
+use tokio::{
+    fs,
+    io::{self, AsyncReadExt as _},
+    task,
+};
+
+use crate::tracks::{
+    format::Format,
+    list::{parse::Entry, List},
+    DecodedData, TrackData, TrackError,
+};
+
+/// Whether a content type is something that could be a track.
+///
+/// Plenty of servers don't know about audio types, so generic binary types are allowed too.
+fn is_audio(content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    mime.starts_with("audio/")
+        || matches!(
+            mime.as_str(),
+            "application/ogg" | "application/octet-stream" | "binary/octet-stream"
+        )
+}
+
+/// Sends a `HEAD` request for a track, falling back to requesting
+/// just the first byte if the server doesn't allow `HEAD`.
+async fn request(client: &Client, full_path: &str) -> reqwest::Result<Response> {
+    let response = client.head(full_path).send().await?;
+    if !matches!(
+        response.status(),
+        StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+    ) {
+        return Ok(response);
+    }
+
+    client
+        .get(full_path)
+        .header(header::RANGE, "bytes=0-0")
+        .send()
+        .await
+}
+
+/// Reads the first few bytes of a file, which is enough to know what format it's in.
+async fn header(path: &str) -> io::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(64);
+    fs::File::open(path)
+        .await?
+        .take(64)
+        .read_to_end(&mut header)
+        .await?;
+
+    Ok(header)
+}
+
+/// Tries to decode a whole file, just like the player would.
+async fn decode(path: &str) -> Result<(), TrackError> {
+    let data = Bytes::from(fs::read(path).await?);
+    let path = path.to_owned();
+
+    task::spawn_blocking(move || {
+        DecodedData::new(TrackData::Full(Cursor::new(data)), &path).map(|_| ())
+    })
+    .await
+    .unwrap_or_else(|error| Err(io::Error::other(error).into()))
+}
+
+/// Checks a local track, making sure that it exists & that it's actually audio.
+///
+/// The format is found just like the player does it, from the contents first and
+/// then the extension, so tracks without one or with the wrong one are still fine.
+async fn check_file(path: &str) -> Option<String> {
+    match fs::metadata(path).await {
+        Ok(metadata) if !metadata.is_file() => return Some(String::from("not a file")),
+        Ok(_) => {}
+        Err(error) => return Some(format!("unable to read file, {error}")),
+    }
+
+    let header = match header(path).await {
+        Ok(header) => header,
+        Err(error) => return Some(format!("unable to read file, {error}")),
+    };
+
+    match Format::from_header(&header).or_else(|| Format::from_extension(path)) {
+        Some(format) if !format.supported() => {
+            Some(format!("{format:?} tracks aren't supported by this build"))
+        }
+        Some(_) => None,
+
+        // Some tracks have junk before the audio starts, so the decoder gets the final say.
+        None => decode(path)
+            .await
+            .err()
+            .map(|error| format!("not audio, {error}")),
+    }
+}
+
+/// Checks a single track, returning what's wrong with it, if anything.
+async fn check_entry(client: &Client, entry: &Entry) -> Option<String> {
+    let full_path = entry.full_path();
+
+    if let Some(path) = full_path.strip_prefix("file://") {
+        return match List::local_path(path) {
+            Ok(path) => check_file(&path).await,
+            Err(error) => Some(error.to_string()),
+        };
+    }
+
+    let response = match request(client, &full_path).await {
+        Ok(response) => response,
+        Err(error) if error.is_timeout() => return Some(String::from("timed out")),
+        Err(error) if error.is_connect() => return Some(String::from("host is unreachable")),
+        Err(error) => return Some(format!("request failed, {error}")),
+    };
+
+    let status = response.status();
+    if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+        return Some(format!("not found ({status})"));
+    } else if !status.is_success() {
+        return Some(format!("server returned {status}"));
+    }
+
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|x| x.to_str().ok());
+
+    match content_type {
+        Some(content_type) if !is_audio(content_type) => {
+            Some(format!("wrong content type {content_type}"))
+        }
+        _ => None,
+    }
+}
+
+/// Gets where a track is, for reporting problems with it.
+fn location(entry: &Entry) -> String {
+    entry.line.map_or_else(
+        || entry.full_path(),
+        |line| format!("line {line}: {}", entry.path),
+    )
+}
+
+/// Checks every track in a list, with at most `parallel` being checked at once.
+///
+/// The list is loaded just like with `--track-list`, so any errors
+/// in the list itself are reported before any tracks are checked.
+pub async fn check(track_list: Option<String>, parallel: usize) -> eyre::Result<()> {
+    let list = List::load(track_list.as_slice(), false).await?;
+    let tracks = list.tracks();
+    let total = tracks.entries.len();
+    let mut problems = 0;
+
+    let mut seen: HashMap<String, &Entry> = HashMap::new();
+    for entry in &tracks.entries {
+        if let Some(first) = seen.get(&entry.full_path()) {
+            problems += 1;
+            println!("{}: duplicate of {}", location(entry), location(first));
+        } else {
+            seen.insert(entry.full_path(), entry);
+        }
+    }
+
+    let client = Client::builder()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .timeout(Duration::from_secs(15))
+        .build()?;
+
+    let mut results = stream::iter(&tracks.entries)
+        .map(|entry| {
+            let client = &client;
+            async move { (entry, check_entry(client, entry).await) }
+        })
+        .buffered(parallel.max(1));
+
+    while let Some((entry, problem)) = results.next().await {
+        if let Some(problem) = problem {
+            problems += 1;
+            println!("{}: {problem}", location(entry));
+        }
+    }
+
+    if problems > 0 {
+        bail!("found {problems} problems in {total} tracks");
+    }
+
+    println!("all {total} tracks are fine");
+    Ok(())
+}
@@ -0,0 +1,219 @@
+//! Has all of the functions for the `download` command, which mirrors
+//! an entire track list onto the local filesystem.
+//!
+//! Each track is saved under the mirror directory using the host & path of its URL,
+//! and then a rewritten list is written which points to the mirror using `file://`,
+//! so that it can be played without the network.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use eyre::{bail, eyre};
+use futures::{stream, StreamExt as _};
+use reqwest::{header, Client, StatusCode};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt as _,
+};
+use url::Url;
+
+use crate::{data_dir, tracks::list::List};
+
+/// Gets the path of a track relative to the mirror directory.
+///
+/// For a full URL, this is the host followed by the path, so that tracks under
+/// different bases never end up in the same file, even if the rest of their paths match.
+fn relative_path(track: &str) -> eyre::Result<PathBuf> {
+    let relative = if track.contains("://") {
+        let url = Url::parse(track)?;
+        format!("{}{}", url.host_str().unwrap_or_default(), url.path())
+    } else {
+        track
+            .split_once(['?', '#'])
+            .map_or(track, |(path, _)| path)
+            .to_owned()
+    };
+
+    let mut path = PathBuf::new();
+    for component in relative.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => bail!("track path {track} escapes the mirror directory"),
+            component => path.push(component),
+        }
+    }
+
+    if path.as_os_str().is_empty() {
+        bail!("track path {track} is empty");
+    }
+
+    Ok(path)
+}
+
+/// Downloads a single track to `destination`, resuming it if a
+/// partial download was left behind by a previous run.
+async fn fetch(client: &Client, url: &str, destination: &Path) -> eyre::Result<()> {
+    if destination.exists() {
+        return Ok(());
+    }
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+
+    let mut partial = destination.as_os_str().to_owned();
+    partial.push(".part");
+    let partial = PathBuf::from(partial);
+
+    let existing = fs::metadata(&partial).await.map_or(0, |x| x.len());
+    let mut request = client.get(url);
+    if existing > 0 {
+        request = request.header(header::RANGE, format!("bytes={existing}-"));
+    }
+
+    let mut response = request.send().await?;
+    let status = response.status();
+
+    // The server saying that the range can't be satisfied
+    // means that the partial file is actually already complete.
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        fs::rename(&partial, destination).await?;
+        return Ok(());
+    }
+
+    if !status.is_success() {
+        bail!("server returned {status}");
+    }
+
+    // Servers which ignore the range will just send the whole file again.
+    let resume = status == StatusCode::PARTIAL_CONTENT;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resume)
+        .truncate(!resume)
+        .open(&partial)
+        .await?;
+
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+    }
+
+    file.flush().await?;
+    drop(file);
+    fs::rename(&partial, destination).await?;
+
+    Ok(())
+}
+
+/// Downloads every track in a list into `directory`, with at most `parallel`
+/// tracks being downloaded at once, and then writes a list pointing to the mirror.
+///
+/// `directory` defaults to `mirror/<name>` in the data directory, and `output`
+/// defaults to `<name>-local.txt` in the data directory, so that it can be
+/// played with `lowfi -t <name>-local`.
+pub async fn download(
+    track_list: Option<String>,
+    directory: Option<PathBuf>,
+    output: Option<PathBuf>,
+    parallel: usize,
+) -> eyre::Result<()> {
+    let list = List::load(track_list.as_slice(), false).await?;
+
+    let directory = match directory {
+        Some(directory) => directory,
+        None => data_dir()?.join("mirror").join(&list.name),
+    };
+    fs::create_dir_all(&directory).await?;
+    let directory = fs::canonicalize(directory).await?;
+
+    let output = match output {
+        Some(output) => output,
+        None => data_dir()?.join(format!("{}-local.txt", list.name)),
+    };
+
+    // There's no overall timeout here, unlike with the player, since the
+    // point of this is to download all of the tracks no matter how long it takes.
+    let client = Client::builder()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .connect_timeout(Duration::from_secs(10))
+        .build()?;
+
+    let tracks = list.tracks();
+    let total = tracks.entries.len();
+
+    let mut results = stream::iter(&tracks.entries)
+        .map(|entry| {
+            let client = &client;
+            let directory = &directory;
+
+            async move {
+                let full_path = entry.full_path();
+
+                // Local files are already where they need to be.
+                if full_path.starts_with("file://") {
+                    return (full_path, entry, Ok(()));
+                }
+
+                let result = match relative_path(&full_path) {
+                    Ok(relative) => fetch(client, &full_path, &directory.join(&relative))
+                        .await
+                        .map(|()| relative.to_string_lossy().replace('\\', "/")),
+                    Err(error) => Err(error),
+                };
+
+                match result {
+                    Ok(relative) => (relative, entry, Ok(())),
+                    Err(error) => (full_path, entry, Err(error)),
+                }
+            }
+        })
+        .buffered(parallel.max(1));
+
+    // The header is kept, so that the mirrored list has the same name & license.
+    let mut lines = Vec::new();
+    if let Some(name) = &list.header.name {
+        lines.push(format!("#name: {name}"));
+    }
+    if let Some(license) = &list.header.license {
+        lines.push(format!("#license: {license}"));
+    }
+    lines.push(format!("file://{}/", directory.display()));
+    let mut failed = 0;
+    let mut done = 0;
+
+    while let Some((path, entry, result)) = results.next().await {
+        done += 1;
+
+        match result {
+            Ok(()) => {
+                println!("[{done}/{total}] {path}");
+                lines.push(entry.to_line(&path));
+            }
+            Err(error) => {
+                failed += 1;
+                eprintln!("[{done}/{total}] failed to download {path}: {error}");
+            }
+        }
+    }
+
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    fs::write(&output, lines.join("\n")).await?;
+    println!("wrote mirrored list to {}", output.display());
+
+    if failed > 0 {
+        return Err(eyre!(
+            "{failed} of {total} tracks failed to download, run this again to retry them"
+        ));
+    }
+
+    Ok(())
+}
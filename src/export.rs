@@ -0,0 +1,27 @@
+//! Has the `export` command, which writes a track list
+//! out as an M3U playlist so that it can be used in other players.
+
+use std::path::PathBuf;
+
+use tokio::fs;
+
+use crate::tracks::list::{playlist, List};
+
+/// Exports a list as an M3U playlist, which is written to `output`, or printed if there isn't one.
+///
+/// `track_list` is resolved the same way as `--track-list`, so any
+/// kind of list can be exported, including directories & other playlists.
+pub async fn export(track_list: Vec<String>, output: Option<PathBuf>) -> eyre::Result<()> {
+    let list = List::load(&track_list, false).await?;
+    let m3u = playlist::to_m3u(list.title(), &list.tracks().entries);
+
+    match output {
+        Some(output) => {
+            fs::write(&output, m3u).await?;
+            eprintln!("wrote playlist to {}", output.display());
+        }
+        None => print!("{m3u}"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,213 @@
+//! Has the `lists` command, which manages the track lists that are
+//! installed in the data directory, so that they can be used by name.
+
+use std::path::Path;
+
+use clap::Subcommand;
+use eyre::{bail, OptionExt as _, WrapErr as _};
+use tokio::fs;
+
+use crate::{
+    data_dir,
+    player::bookmark,
+    tracks::{
+        bag::Bag,
+        list::{remote, List},
+        quarantine::Quarantine,
+    },
+};
+
+/// The different things that the `lists` command can do.
+#[derive(Subcommand, Clone)]
+pub enum Command {
+    /// Shows every installed list, along with how many tracks are in it.
+    List,
+
+    /// Installs a list from a path or URL, so that it can be used by name.
+    Install {
+        /// The path or URL of the list.
+        source: String,
+
+        /// The name to install the list as, defaults to the name of the file.
+        #[clap(long, short)]
+        name: Option<String>,
+
+        /// Replace a list which is already installed with the same name.
+        #[clap(long, short)]
+        force: bool,
+    },
+
+    /// Removes an installed list.
+    Remove {
+        /// The name of the list.
+        name: String,
+    },
+
+    /// Shows the metadata from the top of a list.
+    Show {
+        /// The list, which is resolved the same way as `--track-list`.
+        name: String,
+    },
+
+    /// Clears the quarantine of a list, so that its broken tracks are tried again.
+    Clear {
+        /// The list, which is resolved the same way as `--track-list`.
+        name: String,
+    },
+}
+
+/// Makes sure that `name` can be used as the name of an installed list,
+/// so that it can't point outside of the data directory.
+fn check_name(name: &str) -> eyre::Result<()> {
+    if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+        bail!("invalid list name {name:?}, it can't be empty or contain a path");
+    }
+
+    Ok(())
+}
+
+/// Prints every list in the data directory, sorted by name.
+///
+/// The bookmarks are saved in the same place, but aren't an installed list.
+async fn list() -> eyre::Result<()> {
+    let dir = data_dir()?;
+    let mut names = Vec::new();
+
+    if dir.exists() {
+        let mut entries = fs::read_dir(&dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|x| x == "txt") {
+                names.extend(
+                    path.file_stem()
+                        .and_then(|x| x.to_str())
+                        .filter(|x| *x != bookmark::NAME)
+                        .map(str::to_owned),
+                );
+            }
+        }
+    }
+
+    if names.is_empty() {
+        println!("no lists are installed in {}", dir.display());
+        return Ok(());
+    }
+
+    names.sort_unstable();
+    for name in names {
+        let text = fs::read_to_string(List::installed(&name)?).await?;
+        match List::new(&name, &text) {
+            Ok(list) if list.title() == name => {
+                println!("{name}: {} tracks", list.tracks().entries.len());
+            }
+            Ok(list) => println!(
+                "{name}: {} tracks ({})",
+                list.tracks().entries.len(),
+                list.title()
+            ),
+            Err(error) => println!("{name}: invalid, {error}"),
+        }
+    }
+
+    Ok(())
+}
+
+/// Installs a list from a path or URL as `name`, which is checked to be valid first.
+async fn install(source: &str, name: Option<String>, force: bool) -> eyre::Result<()> {
+    let (stem, text) = if remote::is_remote(source) {
+        remote::load(source, false)
+            .await
+            .wrap_err_with(|| format!("unable to fetch track list {source}"))?
+    } else {
+        let stem = Path::new(source)
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .ok_or_eyre("invalid track path")?;
+
+        (stem.to_owned(), fs::read_to_string(source).await?)
+    };
+
+    let name = name.unwrap_or(stem);
+    check_name(&name)?;
+    List::new(&name, &text).wrap_err_with(|| format!("invalid track list {name}"))?;
+
+    let path = List::installed(&name)?;
+    if path.exists() && !force {
+        bail!("a list called {name} is already installed, use --force to replace it");
+    }
+
+    fs::create_dir_all(data_dir()?).await?;
+    fs::write(&path, text).await?;
+    println!("installed {name}, play it with `lowfi -t {name}`");
+
+    Ok(())
+}
+
+/// Removes an installed list, along with its shuffle bag, sequential position & quarantine.
+async fn remove(name: &str) -> eyre::Result<()> {
+    check_name(name)?;
+    let path = List::installed(name)?;
+    if !path.exists() {
+        bail!("there isn't a list called {name} installed");
+    }
+
+    fs::remove_file(path).await?;
+
+    // The bag is useless without the list, but it doesn't matter if it's not there.
+    let _ = fs::remove_file(Bag::path(name)?).await;
+    let _ = fs::remove_file(List::position_path(name)?).await;
+    let _ = Quarantine::clear(name).await;
+    println!("removed {name}");
+
+    Ok(())
+}
+
+/// Prints the header of a list, along with how many tracks it has.
+async fn show(name: &str) -> eyre::Result<()> {
+    let list = List::load(&[name.to_owned()], false).await?;
+
+    println!("name: {}", list.title());
+    if let Some(license) = &list.header.license {
+        println!("license: {license}");
+    }
+    println!("tracks: {}", list.tracks().entries.len());
+
+    let quarantined = Quarantine::load(&list.id).await?.len();
+    if quarantined > 0 {
+        println!("quarantined: {quarantined}");
+    }
+
+    Ok(())
+}
+
+/// Clears the quarantine of a list, which is resolved just like when it's played.
+///
+/// Lists from URLs aren't fetched again, since only the stored copy is needed to know which list it is.
+async fn clear(name: &str) -> eyre::Result<()> {
+    let list = List::load(&[name.to_owned()], true).await?;
+    let quarantined = Quarantine::load(&list.id).await?.len();
+    if quarantined == 0 {
+        println!("{name} doesn't have any quarantined tracks");
+        return Ok(());
+    }
+
+    Quarantine::clear(&list.id).await?;
+    println!("cleared {quarantined} quarantined tracks from {name}");
+
+    Ok(())
+}
+
+/// Runs the `lists` command, which just lists every installed list if no [`Command`] is given.
+pub async fn lists(command: Option<Command>) -> eyre::Result<()> {
+    match command.unwrap_or(Command::List) {
+        Command::List => list().await,
+        Command::Install {
+            source,
+            name,
+            force,
+        } => install(&source, name, force).await,
+        Command::Remove { name } => remove(&name).await,
+        Command::Show { name } => show(&name).await,
+        Command::Clear { name } => clear(&name).await,
+    }
+}
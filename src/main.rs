@@ -6,7 +6,12 @@ use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
 use eyre::OptionExt;
+use tracks::list::Order;
 
+mod check;
+mod download;
+mod export;
+mod lists;
 mod messages;
 mod play;
 mod player;
@@ -39,6 +44,10 @@ struct Args {
     #[clap(long, short)]
     paused: bool,
 
+    /// Only play tracks which are local or have already been cached.
+    #[clap(long, short)]
+    offline: bool,
+
     /// Include ALSA & other logs.
     #[clap(long, short)]
     debug: bool,
@@ -47,14 +56,45 @@ struct Args {
     #[clap(long, short, default_value_t = 3)]
     width: usize,
 
-    /// Use a custom track list
+    /// Use a custom track list, which can be given more than once to combine
+    /// several, optionally with a ratio like `chillhop:2`
     #[clap(long, short, alias = "list", short_alias = 'l')]
-    track_list: Option<String>,
+    track_list: Vec<String>,
+
+    /// The order to play the tracks of the list in.
+    #[clap(long, short = 'r', value_enum, default_value_t = Order::Random)]
+    order: Order,
 
     /// Internal song buffer size.
     #[clap(long, short = 's', alias = "buffer", default_value_t = 5)]
     buffer_size: usize,
 
+    /// Maximum size of the song buffer, like `64M`, which stops it
+    /// from filling up to `--buffer-size` if the tracks are large.
+    #[clap(long, value_parser = parse_size)]
+    buffer_bytes: Option<usize>,
+
+    /// Minimum amount of audio to keep in the song buffer in seconds,
+    /// which can fill it past `--buffer-size` if the tracks are short.
+    #[clap(long, default_value_t = 0)]
+    buffer_ahead: u64,
+
+    /// How many tracks to download into the buffer at once.
+    #[clap(long, default_value_t = 2)]
+    parallel_downloads: usize,
+
+    /// Maximum size of the on-disk track cache in megabytes, 0 disables it.
+    #[clap(long, short, default_value_t = 512)]
+    cache_size: u64,
+
+    /// Length of the crossfade between tracks in seconds, 0 plays them gaplessly.
+    #[clap(long, short = 'f', default_value_t = 0)]
+    crossfade: u64,
+
+    /// Don't normalize the loudness of tracks.
+    #[clap(long, short)]
+    no_normalize: bool,
+
     /// The command that was ran.
     /// This is [None] if no command was specified.
     #[command(subcommand)]
@@ -74,6 +114,75 @@ enum Commands {
         #[clap(long, short)]
         include_full: bool,
     },
+
+    /// Downloads every track in a track list, so that it can be played offline.
+    Download {
+        /// The track list to download, which is resolved the same way as `--track-list`.
+        track_list: Option<String>,
+
+        /// Where to save the tracks, defaults to `mirror/<list>` in the data directory.
+        #[clap(long, short)]
+        directory: Option<PathBuf>,
+
+        /// Where to write the new track list, defaults to `<list>-local.txt` in the data directory.
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+
+        /// How many tracks to download at once.
+        #[clap(long, short, default_value_t = 4)]
+        parallel: usize,
+    },
+
+    /// Checks every track in a track list, to find any which are missing or broken.
+    Check {
+        /// The track list to check, which is resolved the same way as `--track-list`.
+        track_list: Option<String>,
+
+        /// How many tracks to check at once.
+        #[clap(long, short, default_value_t = 16)]
+        parallel: usize,
+    },
+
+    /// Writes a track list out as an M3U playlist, so that it can be played in other players.
+    Export {
+        /// The track lists to export, which are resolved the same way as `--track-list`.
+        track_list: Vec<String>,
+
+        /// Where to write the playlist, defaults to printing it.
+        #[clap(long, short)]
+        output: Option<PathBuf>,
+    },
+
+    /// Manages the track lists which are installed in the data directory.
+    Lists {
+        /// What to do, defaults to showing every installed list.
+        #[command(subcommand)]
+        command: Option<lists::Command>,
+    },
+}
+
+/// Parses a size in bytes, which can have a `K`, `M` or `G` suffix like `64M`.
+fn parse_size(arg: &str) -> Result<usize, String> {
+    let arg = arg.trim();
+    let (number, unit) = match arg.char_indices().last() {
+        Some((i, suffix)) if suffix.is_ascii_alphabetic() => (&arg[..i], suffix),
+        _ => (arg, 'B'),
+    };
+
+    let multiplier: usize = match unit.to_ascii_uppercase() {
+        'B' => 1,
+        'K' => 1024,
+        'M' => 1024 * 1024,
+        'G' => 1024 * 1024 * 1024,
+        _ => return Err(format!("unknown unit `{unit}`, expected K, M or G")),
+    };
+
+    number
+        .trim()
+        .parse::<usize>()
+        .map_err(|x| x.to_string())?
+        .checked_mul(multiplier)
+        .ok_or_else(|| String::from("size is too large"))
 }
 
 /// Gets lowfi's data directory.
@@ -98,6 +207,18 @@ async fn main() -> eyre::Result<()> {
                 extension,
                 include_full,
             } => scrape::scrape(extension, include_full).await,
+            Commands::Download {
+                track_list,
+                directory,
+                output,
+                parallel,
+            } => download::download(track_list, directory, output, parallel).await,
+            Commands::Check {
+                track_list,
+                parallel,
+            } => check::check(track_list, parallel).await,
+            Commands::Export { track_list, output } => export::export(track_list, output).await,
+            Commands::Lists { command } => lists::lists(command).await,
         }
     } else {
         play::play(cli).await
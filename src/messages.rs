@@ -1,3 +1,5 @@
+use crate::tracks::list::Order;
+
 /// Handles communication between the frontend & audio player.
 #[derive(PartialEq, Debug, Clone, Copy)]
 pub enum Messages {
@@ -9,10 +11,13 @@ pub enum Messages {
     /// been requested but also downloaded aswell.
     NewSong,
 
-    /// This signal is only sent if a track timed out. In that case,
-    /// lowfi will try again and again to retrieve the track.
+    /// This signal is sent if a track couldn't be fetched, once lowfi has waited
+    /// long enough to try again. It'll keep trying until it gets a track.
     TryAgain,
 
+    /// Goes back to the track which was played before the current one.
+    Previous,
+
     /// Similar to Next, but specific to the first track.
     Init,
 
@@ -29,9 +34,32 @@ pub enum Messages {
     /// Change the volume of playback.
     ChangeVolume(f32),
 
+    /// Change the order that tracks are played in.
+    #[cfg_attr(
+        not(feature = "mpris"),
+        allow(dead_code, reason = "the order is only changed by MPRIS clients")
+    )]
+    ChangeOrder(Order),
+
+    /// Toggle whether the current track is repeated.
+    ToggleRepeat,
+
+    /// Seek within the current track.
+    Seek(Seek),
+
     /// Bookmark the current track.
     Bookmark,
 
     /// Quits gracefully.
     Quit,
 }
+
+/// Where to seek to within the current track.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Seek {
+    /// Moves forwards, or backwards if negative, by some amount of seconds.
+    By(f32),
+
+    /// Jumps to a fraction of the way through the track, from 0.0 to 1.0.
+    To(f32),
+}
@@ -129,10 +129,13 @@ pub async fn play(args: Args) -> eyre::Result<()> {
     ui_tx.send(UIEvent::Redraw).await?;
 
     // Actually starts the player.
-    Player::play(Arc::clone(&player), tx.clone(), rx, ui_tx, args.debug).await?;
+    Player::play(Arc::clone(&player), tx.clone(), rx, ui_tx).await?;
 
     // Save the volume.txt file for the next session.
     PersistentVolume::save(player.sink.volume()).await?;
+
+    // Save where the list is up to, even if it was saved recently.
+    player.save(true).await;
     drop(stream.0);
     player.sink.stop();
     ui.and_then(|x| Some(x.abort()));
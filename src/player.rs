@@ -5,33 +5,44 @@
 use std::{
     collections::VecDeque,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use arc_swap::ArcSwapOption;
+use crossfade::{Crossfade, Link, Repeat};
 use downloader::Downloader;
+use history::History;
 use reqwest::Client;
-use rodio::{OutputStream, OutputStreamHandle, Sink};
+use retry::Retry;
+use rodio::{OutputStream, OutputStreamHandle, Sink, Source as _};
 use tokio::{
     select,
     sync::{
         mpsc::{Receiver, Sender},
-        watch, RwLock,
+        watch, Notify, RwLock,
     },
-    task,
+    task::{self, JoinHandle},
     time::interval,
 };
 
+#[cfg(feature = "mpris")]
+use eyre::WrapErr as _;
 #[cfg(feature = "mpris")]
 use mpris_server::{PlaybackStatus, PlayerInterface, Property};
 
 use crate::{
-    messages::Messages,
+    messages::{Messages, Seek},
     play::{PersistentVolume, SendableOutputStream},
-    tracks::{self, list::List},
+    tracks::{
+        self,
+        cache::Cache,
+        list::{List, Order},
+        loudness::Gain,
+        DecodedData,
+    },
     Args,
 };
 
@@ -39,16 +50,28 @@ use ui::UIEvent;
 
 pub mod audio;
 pub mod bookmark;
+pub mod crossfade;
 pub mod downloader;
+pub mod history;
 pub mod queue;
+pub mod retry;
 pub mod ui;
 
 #[cfg(feature = "mpris")]
 pub mod mpris;
 
-/// The time to wait in between errors
+/// How long to wait for a server to respond before giving up
 const TIMEOUT: Duration = Duration::from_secs(3);
 
+/// How many network errors in a row are needed before lowfi falls back to offline mode
+const OFFLINE_THRESHOLD: usize = 3;
+
+/// How long lowfi stays in offline mode after falling back, before trying the network again
+const RECONNECT: Duration = Duration::from_mins(1);
+
+/// How often the list is saved while playing, at most, which happens whenever the track changes
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
 /// Main struct responsible for queuing up & playing tracks
 pub struct Player {
     /// [rodio]'s [`Sink`] which can control playback
@@ -57,6 +80,15 @@ pub struct Player {
     /// The internal buffer size
     pub buffer_size: usize,
 
+    /// The most bytes that the tracks in the buffer can take up, if there's a limit
+    pub buffer_bytes: Option<usize>,
+
+    /// The least amount of audio that should be in the buffer, even if it means going over `buffer_size`
+    pub buffer_ahead: Duration,
+
+    /// How many tracks can be downloaded into the buffer at once
+    pub parallel_downloads: usize,
+
     /// Whether the current track has been bookmarked
     pub bookmarked: AtomicBool,
 
@@ -66,6 +98,32 @@ pub struct Player {
     /// The tracks buffer
     pub tracks: RwLock<VecDeque<tracks::QueuedTrack>>,
 
+    /// Notified by the downloader whenever a track is added to the buffer
+    pub downloaded: Notify,
+
+    /// How many times the buffer has been thrown out, so that the downloader
+    /// can also throw out the tracks which were picked before then
+    pub resets: AtomicUsize,
+
+    /// The [`TrackInfo`] of the track which has been queued in the sink
+    /// to play right after the current one, if there is one
+    pub queued: ArcSwapOption<tracks::Info>,
+
+    /// The tracks which have already been played
+    pub history: History,
+
+    /// The [`Link`] of the track which was most recently appended to the sink
+    link: Mutex<Option<Link>>,
+
+    /// How long tracks should be crossfaded for, zero meaning that they're just played gaplessly
+    pub crossfade: Duration,
+
+    /// Whether the loudness of tracks should be normalized
+    pub normalize: bool,
+
+    /// Whether the current track is being repeated
+    pub repeat: Arc<Repeat>,
+
     /// The actual list of tracks to be played
     pub list: List,
 
@@ -75,6 +133,24 @@ pub struct Player {
     /// The web client
     pub client: Client,
 
+    /// The on-disk cache of downloaded tracks
+    pub cache: Cache,
+
+    /// Whether lowfi was explicitly started in offline mode
+    pub forced_offline: bool,
+
+    /// The amount of network errors in a row
+    failures: AtomicUsize,
+
+    /// When lowfi last fell back to offline mode, if it has
+    fallback: Mutex<Option<Instant>>,
+
+    /// When the list was last saved, if it has been
+    saved: Mutex<Option<Instant>>,
+
+    /// How long to wait before trying again after tracks fail
+    pub retry: Retry,
+
     /// Keep the output stream handle alive
     _handle: OutputStreamHandle,
 
@@ -87,9 +163,18 @@ pub struct Player {
 }
 
 impl Player {
-    /// Just a shorthand for setting `current`
-    fn set_current(&self, info: tracks::Info) {
-        self.current.store(Some(Arc::new(info)));
+    /// Sets `current`, and also records it in the [`History`].
+    fn set_current(&self, info: tracks::Info, track: Option<tracks::QueuedTrack>) {
+        let info = Arc::new(info);
+        self.list.played(&info.full_path);
+        self.history.start(Arc::clone(&info), track);
+        self.current.store(Some(info));
+    }
+
+    /// Whether the downloader fills the buffer at all, which it doesn't
+    /// if both `buffer_size` & `buffer_ahead` are zero
+    pub const fn buffering(&self) -> bool {
+        self.buffer_size > 0 || !self.buffer_ahead.is_zero()
     }
 
     /// A shorthand for checking if `self.current` is [Some]
@@ -97,6 +182,155 @@ impl Player {
         self.current.load().is_some()
     }
 
+    /// Whether only local & cached tracks should be played, either because of
+    /// `--offline` or because there have been too many network errors in a row.
+    pub fn offline(&self) -> bool {
+        self.forced_offline
+            || self
+                .fallback
+                .lock()
+                .unwrap()
+                .is_some_and(|since| since.elapsed() < RECONNECT)
+    }
+
+    /// Keeps track of network errors, falling back to offline mode
+    /// once there have been [`OFFLINE_THRESHOLD`] of them in a row.
+    pub fn record_failure(&self, error: &tracks::TrackError) {
+        if !error.is_network() {
+            return;
+        }
+
+        if self.failures.fetch_add(1, Ordering::Relaxed) + 1 >= OFFLINE_THRESHOLD {
+            self.failures.store(0, Ordering::Relaxed);
+            *self.fallback.lock().unwrap() = Some(Instant::now());
+        }
+    }
+
+    /// Resets the network error count & the retry backoff after a track was successfully fetched.
+    pub fn record_success(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+        self.retry.reset();
+    }
+
+    /// Makes the queued track the current one, which is done once it starts playing.
+    ///
+    /// This only happens if `queued` is still the queued track, so that a
+    /// track can't be promoted twice. Returns whether it was actually promoted.
+    pub fn promote(&self, queued: &Arc<tracks::Info>) -> bool {
+        let expected = Some(Arc::clone(queued));
+        let previous = self.queued.compare_and_swap(&expected, None);
+        if !previous.as_ref().is_some_and(|x| Arc::ptr_eq(x, queued)) {
+            return false;
+        }
+
+        self.list.played(&queued.full_path);
+        self.history.promote(queued);
+        self.current.store(expected);
+        self.bookmarked.store(false, Ordering::Relaxed);
+
+        true
+    }
+
+    /// Whether the list should be saved now, see [`Player::save`], which assumes that it will be.
+    fn should_save(&self, force: bool) -> bool {
+        let mut saved = self.saved.lock().unwrap();
+        if !force && saved.is_some_and(|x| x.elapsed() < SAVE_INTERVAL) {
+            return false;
+        }
+
+        *saved = Some(Instant::now());
+        true
+    }
+
+    /// Saves the list's shuffle bag & sequential position, see [`List::save`].
+    ///
+    /// This is done whenever the track changes, so that they aren't lost if lowfi is killed,
+    /// but it's skipped if it was already done in the last [`SAVE_INTERVAL`] unless `force` is true.
+    pub async fn save(&self, force: bool) {
+        if !self.should_save(force) {
+            return;
+        }
+
+        // Failing to save just means that the shuffle or sequential order will start over next time.
+        let current = self.current.load();
+        let _ = self
+            .list
+            .save(current.as_ref().map(|x| x.full_path.as_str()))
+            .await;
+    }
+
+    /// Appends a track to the sink, connecting it to the track before it if it's being
+    /// queued, so that the two can be crossfaded.
+    ///
+    /// `gain` is the track's loudness normalization, which is ignored if it's been disabled.
+    /// `on_start` is called once the track actually starts playing.
+    fn append(
+        &self,
+        data: DecodedData,
+        gain: Gain,
+        queued: bool,
+        on_start: Option<Box<dyn FnOnce() + Send>>,
+    ) {
+        let incoming = if queued {
+            self.link.lock().unwrap().take()
+        } else {
+            None
+        };
+
+        let (channels, sample_rate) = (data.channels(), data.sample_rate());
+        let gain = if self.normalize {
+            gain
+        } else {
+            Gain::Known(1.0)
+        };
+        let (source, outgoing) = Crossfade::new(
+            data,
+            gain,
+            self.crossfade,
+            incoming.clone(),
+            on_start,
+            Arc::clone(&self.repeat),
+        );
+        *self.link.lock().unwrap() = Some(outgoing);
+        self.sink.append(source);
+
+        if let Some(incoming) = incoming {
+            crossfade::connect(&incoming, channels, sample_rate);
+        }
+    }
+
+    /// Gets the position within the current track, taking into account any times that it's been repeated.
+    pub fn position(&self) -> Duration {
+        self.sink.get_pos().saturating_sub(self.repeat.offset())
+    }
+
+    /// Seeks within the current track, returning whether it actually worked.
+    ///
+    /// The new position is clamped to the duration of the track, if it's known.
+    /// Jumping to a fraction of the track needs the duration, though.
+    pub fn seek(&self, seek: Seek) -> bool {
+        let current = self.current.load();
+        let Some(current) = current.as_ref() else {
+            return false;
+        };
+
+        let position = self.position();
+        let target = match seek {
+            Seek::By(seconds) if seconds < 0.0 => {
+                position.saturating_sub(Duration::from_secs_f32(-seconds))
+            }
+            Seek::By(seconds) => position + Duration::from_secs_f32(seconds),
+            Seek::To(fraction) => match current.duration {
+                Some(duration) => duration.mul_f32(fraction.clamp(0.0, 1.0)),
+                None => return false,
+            },
+        };
+        let target = current.duration.map_or(target, |x| target.min(x));
+
+        // Seeking waits for the audio thread to pick it up.
+        task::block_in_place(|| self.sink.try_seek(target)).is_ok()
+    }
+
     /// Sets the volume of the sink, clamping 0.0..1.0
     pub fn set_volume(&self, volume: f32) {
         self.sink.set_volume(volume.clamp(0.0, 1.0));
@@ -121,7 +355,11 @@ impl Player {
         let volume = PersistentVolume::load().await?;
 
         // Load the track list
-        let list = List::load(args.track_list.as_ref()).await?;
+        let mut list = List::load(&args.track_list, args.offline).await?;
+        list.start(args.order).await?;
+
+        // Open the track cache, `cache_size` is in megabytes
+        let cache = Cache::new(args.cache_size * 1024 * 1024).await?;
 
         // Setup audio output stream
         #[cfg(target_os = "linux")]
@@ -150,12 +388,29 @@ impl Player {
         let player = Self {
             sink,
             buffer_size: args.buffer_size,
+            buffer_bytes: args.buffer_bytes,
+            buffer_ahead: Duration::from_secs(args.buffer_ahead),
+            parallel_downloads: args.parallel_downloads,
             bookmarked: AtomicBool::new(false),
             current: ArcSwapOption::new(None),
             tracks: RwLock::new(VecDeque::with_capacity(args.buffer_size)),
+            downloaded: Notify::new(),
+            resets: AtomicUsize::new(0),
+            queued: ArcSwapOption::new(None),
+            history: History::default(),
+            link: Mutex::new(None),
+            crossfade: Duration::from_secs(args.crossfade),
+            normalize: !args.no_normalize,
+            repeat: Arc::default(),
             list,
             volume,
             client,
+            cache,
+            forced_offline: args.offline,
+            failures: AtomicUsize::new(0),
+            fallback: Mutex::new(None),
+            saved: Mutex::new(None),
+            retry: Retry::default(),
             _handle: handle,
             progress_tx,
             emit_progress: AtomicBool::new(true),
@@ -175,26 +430,164 @@ impl Player {
             is_paused: self.sink.is_paused(),
             is_playing: self.current_exists() && !self.sink.is_paused(),
             volume: self.sink.volume(),
-            position: self.sink.get_pos(),
+            position: self.position(),
             is_bookmarked: self.bookmarked.load(Ordering::Relaxed),
         }
     }
 
+    /// Stops queueing up the next track ahead of time, if that's happening.
+    fn stop_preparing(preparing: &mut Option<JoinHandle<eyre::Result<()>>>) {
+        if let Some(preparing) = preparing.take() {
+            preparing.abort();
+        }
+    }
+
+    /// Handles a `NewSong` signal, which also queues up the track after the new one
+    /// so that it's ready before the new one ends.
+    async fn new_song(
+        player: &Arc<Self>,
+        preparing: &mut Option<JoinHandle<eyre::Result<()>>>,
+        itx: &Sender<()>,
+        tx: &Sender<Messages>,
+        listeners: &Listeners<'_>,
+    ) -> eyre::Result<()> {
+        Self::stop_preparing(preparing);
+        *preparing = Some(task::spawn(Self::prepare(
+            Arc::clone(player),
+            itx.clone(),
+            tx.clone(),
+        )));
+
+        Self::send_ui_event(listeners.ui_tx, UIEvent::TrackChanged).await;
+        player.save(false).await;
+
+        #[cfg(feature = "mpris")]
+        {
+            let mpris = listeners.mpris;
+            mpris
+                .changed(vec![
+                    Property::Metadata(mpris.player().metadata().await?),
+                    Property::PlaybackStatus(mpris.player().playback_status().await?),
+                    Property::CanSeek(mpris.player().can_seek().await?),
+                    Property::CanGoPrevious(mpris.player().can_go_previous().await?),
+                ])
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Handles a `Previous` signal, returning whether the previous track is now being loaded.
+    ///
+    /// Nothing happens if there's nowhere to go back to, or if a track is still loading.
+    async fn go_back(
+        player: &Arc<Self>,
+        preparing: &mut Option<JoinHandle<eyre::Result<()>>>,
+        tx: &Sender<Messages>,
+        ui_tx: &Sender<UIEvent>,
+    ) -> bool {
+        if player.history.is_empty() || !player.current_exists() {
+            return false;
+        }
+
+        player.bookmarked.swap(false, Ordering::Relaxed);
+        Self::stop_preparing(preparing);
+        Self::send_ui_event(ui_tx, UIEvent::TrackChanged).await;
+        task::spawn(Self::previous(Arc::clone(player), tx.clone()));
+
+        true
+    }
+
+    /// Handles a `ChangeOrder` signal.
+    ///
+    /// The buffered tracks were picked in the old order, so they're thrown out.
+    async fn reorder(
+        &self,
+        order: Order,
+        itx: &Sender<()>,
+        #[cfg_attr(
+            not(feature = "mpris"),
+            allow(unused_variables, reason = "the order is only shown over MPRIS")
+        )]
+        listeners: &Listeners<'_>,
+    ) -> eyre::Result<()> {
+        if self.list.order() != order {
+            self.list.set_order(order);
+            self.reset().await;
+            Downloader::notify(itx).await?;
+        }
+
+        #[cfg(feature = "mpris")]
+        listeners
+            .mpris
+            .changed(vec![Property::Shuffle(
+                listeners.mpris.player().shuffle().await?,
+            )])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Handles a `Bookmark` signal, bookmarking the current track or unbookmarking it if it already was.
+    async fn toggle_bookmark(&self, ui_tx: &Sender<UIEvent>) -> eyre::Result<()> {
+        let loaded = self.current.load();
+        let current = loaded.as_ref().unwrap().clone();
+        let bookmarked = bookmark::bookmark(
+            current.full_path.clone(),
+            if current.custom_name {
+                Some(current.display_name.clone())
+            } else {
+                None
+            },
+        )
+        .await?;
+        self.bookmarked.swap(bookmarked, Ordering::Relaxed);
+        Self::send_ui_event(ui_tx, UIEvent::BookmarkChanged).await;
+
+        Ok(())
+    }
+
+    /// Handles a `ToggleRepeat` signal.
+    async fn toggle_repeat(&self, listeners: &Listeners<'_>) -> eyre::Result<()> {
+        self.repeat.enabled.fetch_xor(true, Ordering::Relaxed);
+        Self::send_ui_event(listeners.ui_tx, UIEvent::PlaybackStateChanged).await;
+
+        #[cfg(feature = "mpris")]
+        listeners
+            .mpris
+            .changed(vec![Property::LoopStatus(
+                listeners.mpris.player().loop_status().await?,
+            )])
+            .await?;
+
+        Ok(())
+    }
+
+    /// Handles a `Seek` signal, only telling the listeners if the seek actually worked.
+    async fn seek_to(&self, seek: Seek, listeners: &Listeners<'_>) -> eyre::Result<()> {
+        if self.seek(seek) {
+            Self::send_ui_event(listeners.ui_tx, UIEvent::ProgressUpdate).await;
+            #[cfg(feature = "mpris")]
+            listeners.mpris.seeked(self.position()).await?;
+        }
+
+        Ok(())
+    }
+
     /// This is the main "audio server"
     pub async fn play(
         player: Arc<Self>,
         tx: Sender<Messages>,
         mut rx: Receiver<Messages>,
         ui_tx: Sender<UIEvent>,
-        debug: bool,
     ) -> eyre::Result<()> {
         #[cfg(feature = "mpris")]
         let mpris = mpris::Server::new(Arc::clone(&player), tx.clone())
             .await
-            .inspect_err(|x| dbg!(x))?;
+            .wrap_err("unable to start the MPRIS server")?;
 
         let downloader = Downloader::new(Arc::clone(&player));
-        let (itx, downloader) = downloader.start(debug);
+        let (itx, downloader) = downloader.start();
 
         Downloader::notify(&itx).await?;
         player.set_volume(player.volume.float());
@@ -208,15 +601,23 @@ impl Player {
         let progress_task = task::spawn(async move {
             loop {
                 progress_interval.tick().await;
+                // The UI is also updated while waiting to retry, so that the countdown is shown.
                 if p.emit_progress.load(Ordering::Relaxed)
-                    && p.current_exists()
-                    && !p.sink.is_paused()
+                    && ((p.current_exists() && !p.sink.is_paused()) || p.retry.waiting())
                 {
                     let _ = progress_tx.send(UIEvent::ProgressUpdate);
                 }
             }
         });
 
+        // The task which is queueing up the next track ahead of time, if there is one.
+        let mut preparing: Option<JoinHandle<eyre::Result<()>>> = None;
+        let listeners = Listeners {
+            ui_tx: &ui_tx,
+            #[cfg(feature = "mpris")]
+            mpris: &mpris,
+        };
+
         let mut new = false;
         loop {
             let clone = Arc::clone(&player);
@@ -231,16 +632,17 @@ impl Player {
                 Messages::Next | Messages::Init | Messages::TryAgain => {
                     player.bookmarked.swap(false, Ordering::Relaxed);
                     new = false;
+                    Self::stop_preparing(&mut preparing);
                     if msg == Messages::Next && !player.current_exists() {
                         continue;
                     }
                     Self::send_ui_event(&ui_tx, UIEvent::TrackChanged).await;
-                    task::spawn(Self::next(
-                        Arc::clone(&player),
-                        itx.clone(),
-                        tx.clone(),
-                        debug,
-                    ));
+                    task::spawn(Self::next(Arc::clone(&player), itx.clone(), tx.clone()));
+                }
+                Messages::Previous => {
+                    if Self::go_back(&player, &mut preparing, &tx, &ui_tx).await {
+                        new = false;
+                    }
                 }
                 Messages::Play => {
                     player.sink.play();
@@ -276,42 +678,36 @@ impl Player {
                 }
                 Messages::NewSong => {
                     new = true;
-                    Self::send_ui_event(&ui_tx, UIEvent::TrackChanged).await;
-                    #[cfg(feature = "mpris")]
-                    mpris
-                        .changed(vec![
-                            Property::Metadata(mpris.player().metadata().await?),
-                            Property::PlaybackStatus(mpris.player().playback_status().await?),
-                        ])
-                        .await?;
+                    Self::new_song(&player, &mut preparing, &itx, &tx, &listeners).await?;
                     continue;
                 }
-                Messages::Bookmark => {
-                    let loaded = player.current.load();
-                    let current = loaded.as_ref().unwrap().clone();
-                    let bookmarked = bookmark::bookmark(
-                        current.full_path.clone(),
-                        if current.custom_name {
-                            Some(current.display_name.clone())
-                        } else {
-                            None
-                        },
-                    )
-                    .await?;
-                    player.bookmarked.swap(bookmarked, Ordering::Relaxed);
-                    Self::send_ui_event(&ui_tx, UIEvent::BookmarkChanged).await;
-                }
+                Messages::ChangeOrder(order) => player.reorder(order, &itx, &listeners).await?,
+                Messages::ToggleRepeat => player.toggle_repeat(&listeners).await?,
+                Messages::Seek(seek) => player.seek_to(seek, &listeners).await?,
+                Messages::Bookmark => player.toggle_bookmark(&ui_tx).await?,
                 Messages::Quit => break,
             }
         }
 
         downloader.abort();
         progress_task.abort();
+        Self::stop_preparing(&mut preparing);
 
         Ok(())
     }
 }
 
+/// Everything which needs to be told about changes in playback,
+/// which is used by the message handlers in [`Player::play`].
+struct Listeners<'a> {
+    /// The UI's event channel.
+    ui_tx: &'a Sender<UIEvent>,
+
+    /// The MPRIS server.
+    #[cfg(feature = "mpris")]
+    mpris: &'a mpris::Server,
+}
+
 /// Playback information for UI components
 #[derive(Debug, Clone, Copy)]
 pub struct PlaybackInfo {
@@ -5,6 +5,10 @@ use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 
 use crate::data_dir;
 
+/// The name of the file that bookmarks are saved in, which is in the data directory
+/// next to the installed lists, but without the `.txt` extension.
+pub const NAME: &str = "bookmarks";
+
 /// Bookmarks a given track with a full path and optional custom name.
 ///
 /// Returns whether the track is now bookmarked, or not.
@@ -25,7 +29,7 @@ pub async fn bookmark(path: String, custom: Option<String>) -> eyre::Result<bool
         .write(true)
         .read(true)
         .append(false)
-        .open(data_dir.join("bookmarks.txt"))
+        .open(data_dir.join(format!("{NAME}.txt")))
         .await?;
 
     let mut text = String::new();
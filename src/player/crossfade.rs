@@ -0,0 +1,294 @@
+//! Contains [`Crossfade`], which wraps each track that's appended to the sink so
+//! that the next track can be queued up ahead of time, and then either played gaplessly
+//! or mixed into the end of the current one.
+//!
+//! Since the sink only ever plays one source at a time, crossfading works by having
+//! the outgoing track end early and hand the rest of itself over to the incoming
+//! track, which then mixes it in while fading it out.
+//!
+//! This is also where tracks are repeated, by seeking back to the start once they end.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use rodio::{
+    source::{Amplify, SeekError, UniformSourceIterator},
+    Source,
+};
+
+use crate::tracks::{loudness::Gain, DecodedData};
+
+/// A track's decoded data, with its loudness normalized.
+type Inner = Amplify<DecodedData>;
+
+/// The end of a track which has been handed off, converted to the format of the next track.
+type Tail = UniformSourceIterator<Inner, i16>;
+
+/// The connection between a track and the one which was queued after it.
+#[derive(Default)]
+pub struct Handoff {
+    /// The channels & sample rate of the next track, once it's been queued.
+    next: Option<(u16, u32)>,
+
+    /// The end of the previous track, which is put here once it starts fading out.
+    tail: Option<Tail>,
+}
+
+/// A shared [`Handoff`], held by both tracks on either side of it.
+pub type Link = Arc<Mutex<Handoff>>;
+
+/// Controls whether the current track is repeated, which is shared between every track.
+#[derive(Default)]
+pub struct Repeat {
+    /// Whether the current track should start over once it ends, instead of moving on.
+    pub enabled: AtomicBool,
+
+    /// How long the current track had been playing for in milliseconds before it last started over,
+    /// which has to be subtracted from the position of the sink since it doesn't know about it.
+    offset: AtomicU64,
+}
+
+impl Repeat {
+    /// Gets the offset which should be subtracted from the sink's position.
+    pub fn offset(&self) -> Duration {
+        Duration::from_millis(self.offset.load(Ordering::Relaxed))
+    }
+}
+
+/// Tells the track on the other side of `link` about the track after it, so that it can hand off to it.
+///
+/// This should only be done once the next track has actually been appended to the sink,
+/// since the handoff can happen immediately.
+pub fn connect(link: &Link, channels: u16, sample_rate: u32) {
+    link.lock().unwrap().next = Some((channels, sample_rate));
+}
+
+/// Converts a duration into an amount of samples, where `rate` is the amount of samples per second.
+fn samples(duration: Duration, rate: u64) -> u64 {
+    u64::try_from(duration.as_millis() * u128::from(rate) / 1000).unwrap_or(u64::MAX)
+}
+
+/// Wraps a track's decoded data so that it can be crossfaded with the tracks around it.
+pub struct Crossfade {
+    /// The actual decoded data, which is [None] once it's been handed off.
+    inner: Option<Inner>,
+
+    /// The track's gain, if it was still being measured when the track was appended.
+    /// It's applied as soon as the measurement is done, which is usually very early on.
+    pending: Option<Gain>,
+
+    /// How many samples per second the track has, counting every channel.
+    rate: u64,
+
+    /// How many samples have been played so far.
+    played: u64,
+
+    /// The sample at which the track should hand off to the next one.
+    /// This is [None] if crossfading is disabled or the duration is unknown,
+    /// in which case the track is simply played to the end.
+    end: Option<u64>,
+
+    /// The connection to the track after this one.
+    outgoing: Link,
+
+    /// The connection to the track before this one, which is
+    /// checked for a tail as soon as this track starts playing.
+    incoming: Option<Link>,
+
+    /// The end of the previous track, which is mixed in while fading out.
+    tail: Option<Tail>,
+
+    /// How long the fade is, in samples.
+    fade: u64,
+
+    /// Called once the track actually starts playing.
+    on_start: Option<Box<dyn FnOnce() + Send>>,
+
+    /// Whether the track has started playing yet.
+    started: bool,
+
+    /// Whether the track should be repeated.
+    repeat: Arc<Repeat>,
+}
+
+impl Crossfade {
+    /// Wraps `data`, returning the [`Crossfade`] along with the [`Link`] that
+    /// should be given to the next track.
+    ///
+    /// `gain` is multiplied with every sample to normalize the track's loudness, `incoming` is the [`Link`] of the track which was appended right before this one,
+    /// if there is one, and `on_start` is called once this track starts playing.
+    pub fn new(
+        data: DecodedData,
+        gain: Gain,
+        crossfade: Duration,
+        incoming: Option<Link>,
+        on_start: Option<Box<dyn FnOnce() + Send>>,
+        repeat: Arc<Repeat>,
+    ) -> (Self, Link) {
+        let rate = u64::from(data.sample_rate()) * u64::from(data.channels());
+
+        let end = data
+            .total_duration()
+            .filter(|_| !crossfade.is_zero())
+            .and_then(|x| x.checked_sub(crossfade))
+            .map(|x| samples(x, rate));
+
+        let multiplier = gain.get();
+        let outgoing = Link::default();
+        let crossfade = Self {
+            inner: Some(data.amplify(multiplier.unwrap_or(1.0))),
+            pending: multiplier.is_none().then_some(gain),
+            rate,
+            played: 0,
+            end,
+            outgoing: Arc::clone(&outgoing),
+            incoming,
+            tail: None,
+            fade: samples(crossfade, rate),
+            on_start,
+            started: false,
+            repeat,
+        };
+
+        (crossfade, outgoing)
+    }
+
+    /// Hands the rest of the track over to the next one, if it's been queued.
+    /// Returns whether the track was handed off.
+    fn handoff(&mut self) -> bool {
+        let mut handoff = self.outgoing.lock().unwrap();
+        let Some((channels, sample_rate)) = handoff.next else {
+            return false;
+        };
+
+        if let Some(inner) = self.inner.take() {
+            handoff.tail = Some(UniformSourceIterator::new(inner, channels, sample_rate));
+        }
+
+        true
+    }
+
+    /// Whether the track should be repeated.
+    fn repeating(&self) -> bool {
+        self.repeat.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Seeks back to the start of the track, returning whether that worked.
+    fn restart(&mut self) -> bool {
+        let Some(inner) = &mut self.inner else {
+            return false;
+        };
+
+        if inner.try_seek(Duration::ZERO).is_err() {
+            return false;
+        }
+
+        let played = self.played.saturating_mul(1000) / self.rate.max(1);
+        self.repeat.offset.fetch_add(played, Ordering::Relaxed);
+        self.played = 0;
+
+        true
+    }
+}
+
+impl Iterator for Crossfade {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(incoming) = self.incoming.take() {
+            self.tail = incoming.lock().unwrap().tail.take();
+        }
+
+        if !self.started {
+            self.started = true;
+            self.repeat.offset.store(0, Ordering::Relaxed);
+        }
+
+        if let Some(on_start) = self.on_start.take() {
+            on_start();
+        }
+
+        // If the next track hasn't been queued in time, then this just plays to the end.
+        // Tracks which are being repeated also play to the end, and then start over.
+        if self.end.is_some_and(|end| self.played >= end) && !self.repeating() {
+            if self.handoff() {
+                return None;
+            }
+
+            self.end = None;
+        }
+
+        if let Some(multiplier) = self.pending.as_ref().and_then(Gain::get) {
+            self.pending = None;
+            if let Some(inner) = &mut self.inner {
+                inner.set_factor(multiplier);
+            }
+        }
+
+        let sample = match self.inner.as_mut()?.next() {
+            Some(sample) => sample,
+            None if self.repeating() && self.restart() => self.inner.as_mut()?.next()?,
+            None => return None,
+        };
+        self.played += 1;
+
+        if self.played > self.fade {
+            self.tail = None;
+        }
+
+        let Some(tail) = self.tail.as_mut().and_then(Iterator::next) else {
+            self.tail = None;
+            return Some(sample);
+        };
+
+        #[expect(
+            clippy::cast_precision_loss,
+            reason = "this is only used as a volume, so precision doesn't matter"
+        )]
+        let progress = self.played as f32 / self.fade as f32;
+        let mixed = f32::from(sample).mul_add(progress, f32::from(tail) * (1.0 - progress));
+
+        #[expect(
+            clippy::cast_possible_truncation,
+            reason = "the sample is clamped to the range of an i16"
+        )]
+        Some(mixed.clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16)
+    }
+}
+
+impl Source for Crossfade {
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.as_ref().and_then(Source::current_frame_len)
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.as_ref().map_or(1, Source::channels)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.as_ref().map_or(44100, Source::sample_rate)
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.as_ref().and_then(Source::total_duration)
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let Some(inner) = &mut self.inner else {
+            return Ok(());
+        };
+
+        inner.try_seek(pos)?;
+        self.played = samples(pos, self.rate);
+
+        // The sink knows about seeks, so its position is correct again.
+        self.repeat.offset.store(0, Ordering::Relaxed);
+
+        Ok(())
+    }
+}
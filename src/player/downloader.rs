@@ -1,14 +1,21 @@
 //! Contains the [`Downloader`] struct.
 
-use std::sync::Arc;
+use std::{
+    collections::VecDeque,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
+use futures::{stream::FuturesOrdered, StreamExt as _};
 use tokio::{
+    select,
     sync::mpsc::{self, Receiver, Sender},
     task::{self, JoinHandle},
-    time::sleep,
 };
 
-use super::{Player, TIMEOUT};
+use crate::tracks::{list::parse::Entry, QueuedTrack, TrackError};
+
+use super::Player;
 
 /// This struct is responsible for downloading tracks in the background.
 ///
@@ -24,6 +31,10 @@ pub struct Downloader {
     /// A copy of the internal sender, which can be useful for keeping
     /// track of it.
     tx: Sender<()>,
+
+    /// The size of the last track which was downloaded, which is used to guess
+    /// how big the next ones will be when the buffer is empty.
+    size: Option<usize>,
 }
 
 impl Downloader {
@@ -39,35 +50,139 @@ impl Downloader {
     /// when the downloader needs to begin downloading more tracks.
     pub fn new(player: Arc<Player>) -> Self {
         let (tx, rx) = mpsc::channel(8);
-        Self { player, rx, tx }
+        Self {
+            player,
+            rx,
+            tx,
+            size: None,
+        }
     }
 
-    /// Push a new, random track onto the internal buffer.
-    pub async fn push_buffer(&self, debug: bool) {
-        let data = self.player.list.random(&self.player.client).await;
-        match data {
-            Ok(track) => self.player.tracks.write().await.push_back(track),
-            Err(error) if !error.is_timeout() => {
-                if debug {
-                    panic!("{}", error)
-                }
+    /// Downloads a track, or just waits if it couldn't be picked.
+    ///
+    /// If this fails, then it waits according to the [`Player`]'s retry policy before returning,
+    /// so that the download keeps its slot & the downloader doesn't spin on errors.
+    /// `resets` is how many times the buffer had been reset when the track was picked, which is given back.
+    async fn download(
+        player: Arc<Player>,
+        entry: Result<Entry, TrackError>,
+        resets: usize,
+    ) -> (usize, Option<QueuedTrack>) {
+        let result = match entry {
+            Ok(entry) => player.download(&entry).await,
+            Err(error) => Err(error),
+        };
 
-                sleep(TIMEOUT).await;
+        match result {
+            Ok(track) => (resets, Some(track)),
+            Err(error) => {
+                player.retry.wait(&error).await;
+                (resets, None)
             }
-            _ => {}
         }
     }
 
+    /// Whether the buffer should get another track, see [`Downloader::wants`].
+    async fn wants_more(&self, pending: usize) -> bool {
+        let tracks = self.player.tracks.read().await;
+        self.wants(&tracks, pending)
+    }
+
+    /// Whether the buffer should get another track, when `pending` tracks are already being downloaded.
+    ///
+    /// The buffer wants more tracks until it has both `buffer_size` tracks & `buffer_ahead` of audio.
+    /// If there's a `buffer_bytes` limit, then the new track & every pending one also have to be expected
+    /// to fit, going by the average size of the tracks in the buffer or the size of the last track.
+    /// Until a track has been downloaded there's nothing to go by, so only one is downloaded at a time,
+    /// and there's always room for at least one track.
+    fn wants(&self, tracks: &VecDeque<QueuedTrack>, pending: usize) -> bool {
+        let ahead: Duration = tracks.iter().filter_map(|x| x.duration.get()).sum();
+        if tracks.len() + pending >= self.player.buffer_size && ahead >= self.player.buffer_ahead {
+            return false;
+        }
+
+        let Some(limit) = self.player.buffer_bytes else {
+            return true;
+        };
+
+        if tracks.is_empty() && pending == 0 {
+            return true;
+        }
+
+        let bytes: usize = tracks.iter().map(|x| x.data.len()).sum();
+        let Some(estimate) = bytes.checked_div(tracks.len()).or(self.size) else {
+            return false;
+        };
+
+        bytes + estimate * (pending + 1) <= limit
+    }
+
+    /// Push a downloaded track onto the internal buffer.
+    ///
+    /// Tracks are never thrown away once they've been downloaded, since they've already been
+    /// picked & skipping them would break the order. Instead, [`Downloader::wants`] makes sure
+    /// that no more tracks are started than the buffer has room for.
+    /// The only exception is if the buffer was reset after the track was picked, see [`Player::reset`].
+    async fn push_buffer(&mut self, track: QueuedTrack, resets: usize) {
+        self.size = Some(track.data.len());
+
+        // The duration is only needed to know how much audio is in the buffer.
+        if !self.player.buffer_ahead.is_zero() {
+            let track = track.clone();
+            let _ = task::spawn_blocking(move || track.measure()).await;
+        }
+
+        // The loudness is measured while the track waits in the buffer.
+        if self.player.normalize {
+            let track = track.clone();
+            task::spawn_blocking(move || track.analyze());
+        }
+
+        let mut tracks = self.player.tracks.write().await;
+        if self.player.resets.load(Ordering::Relaxed) != resets {
+            return;
+        }
+
+        tracks.push_back(track);
+        drop(tracks);
+        self.player.downloaded.notify_one();
+    }
+
     /// Actually starts & consumes the [Downloader].
-    pub fn start(mut self, debug: bool) -> (Sender<()>, JoinHandle<()>) {
+    ///
+    /// Up to `parallel_downloads` tracks are downloaded at once, although never more than
+    /// the buffer wants. Tracks are added to the buffer in the order they were picked,
+    /// rather than the order their downloads finish in, so that sequential order is kept.
+    ///
+    /// Aborting the returned task cancels any downloads that are still going.
+    pub fn start(mut self) -> (Sender<()>, JoinHandle<()>) {
         let tx = self.tx.clone();
 
         let handle = task::spawn(async move {
-            // Loop through each update notification.
-            while self.rx.recv().await == Some(()) {
-                //  For each update notification, we'll push tracks until the buffer is completely full.
-                while self.player.tracks.read().await.len() < self.player.buffer_size {
-                    self.push_buffer(debug).await;
+            let mut downloads = FuturesOrdered::new();
+
+            loop {
+                // Start as many downloads as there's room for, counting the ones which haven't finished yet.
+                // Tracks are picked here instead of in the download itself, so that they're picked in order.
+                while downloads.len() < self.player.parallel_downloads.max(1)
+                    && self.wants_more(downloads.len()).await
+                {
+                    let resets = self.player.resets.load(Ordering::Relaxed);
+                    let entry = self.player.pick().await;
+                    downloads.push_back(Self::download(Arc::clone(&self.player), entry, resets));
+                }
+
+                select! {
+                    notification = self.rx.recv() => {
+                        if notification.is_none() {
+                            break;
+                        }
+                    }
+                    Some((resets, track)) = downloads.next(), if !downloads.is_empty() => {
+                        if let Some(track) = track {
+                            self.push_buffer(track, resets).await;
+                        }
+                    }
                 }
             }
         });
@@ -0,0 +1,104 @@
+//! Contains [`History`], which remembers the tracks that have already been
+//! played so that it's possible to go back to them.
+
+use std::{collections::VecDeque, sync::Arc, sync::Mutex};
+
+use crate::tracks::{Info, QueuedTrack};
+
+/// The most tracks that are remembered at once.
+const SIZE: usize = 32;
+
+/// How many of the most recently played tracks keep their data around,
+/// the rest have to be fetched again if they're played.
+const KEEP_DATA: usize = 4;
+
+/// A single track that has been played.
+#[derive(Clone)]
+pub struct Entry {
+    /// The info of the track, which was shown while it was playing.
+    pub info: Arc<Info>,
+
+    /// The undecoded track itself, which is [None] if it was streamed
+    /// or if it's been evicted to save memory.
+    pub track: Option<QueuedTrack>,
+}
+
+/// The inner state of [`History`].
+#[derive(Default)]
+struct Inner {
+    /// Every track which was played before the current one, with the most recent at the back.
+    played: VecDeque<Entry>,
+
+    /// The track that's currently playing, which is only added to `played` once it's replaced.
+    current: Option<Entry>,
+
+    /// The track which has been queued up to play after the current one.
+    queued: Option<Entry>,
+}
+
+/// A bounded history of the tracks that have been played.
+#[derive(Default)]
+pub struct History {
+    /// The actual state, behind a lock.
+    inner: Mutex<Inner>,
+}
+
+impl History {
+    /// Whether there are any tracks to go back to.
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().played.is_empty()
+    }
+
+    /// Remembers the track that's been queued up, so that its data
+    /// is available once it starts playing with [`History::promote`].
+    pub fn queue(&self, info: Arc<Info>, track: QueuedTrack) {
+        self.inner.lock().unwrap().queued = Some(Entry {
+            info,
+            track: Some(track),
+        });
+    }
+
+    /// Records that a track has started playing, moving the previous one into the history.
+    pub fn start(&self, info: Arc<Info>, track: Option<QueuedTrack>) {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = Entry { info, track };
+
+        if let Some(previous) = inner.current.replace(entry) {
+            inner.played.push_back(previous);
+        }
+
+        if inner.played.len() > SIZE {
+            inner.played.pop_front();
+        }
+
+        // Older tracks only keep their info, since the data takes up a lot of memory.
+        let evicted = inner.played.len().saturating_sub(KEEP_DATA);
+        for entry in inner.played.range_mut(..evicted) {
+            entry.track = None;
+        }
+    }
+
+    /// Records that the queued track has started playing, just like [`History::start`].
+    pub fn promote(&self, info: &Arc<Info>) {
+        let queued = self.inner.lock().unwrap().queued.take();
+        let track = queued
+            .filter(|x| Arc::ptr_eq(&x.info, info))
+            .and_then(|x| x.track);
+
+        self.start(Arc::clone(info), track);
+    }
+
+    /// Takes the most recently played track out of the history, so that it can be played again.
+    ///
+    /// The current track is forgotten about, since otherwise
+    /// going back twice would just end up at the same track.
+    pub fn back(&self) -> Option<Entry> {
+        let mut inner = self.inner.lock().unwrap();
+        let entry = inner.played.pop_back()?;
+        inner.current = None;
+        inner.queued = None;
+        drop(inner);
+
+        Some(entry)
+    }
+}
@@ -1,19 +1,35 @@
 //! Contains the code for the MPRIS server & other helper functions.
 
-use std::{env, process, sync::Arc};
+use std::{
+    env, process,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
 use mpris_server::{
     zbus::{self, fdo, Result},
     LoopStatus, Metadata, PlaybackRate, PlaybackStatus, PlayerInterface, Property, RootInterface,
-    Time, TrackId, Volume,
+    Signal, Time, TrackId, Volume,
 };
 use tokio::sync::mpsc::Sender;
 
 use super::ui;
 use super::Messages;
+use crate::messages::Seek;
+use crate::tracks::{self, cache::Cache, format::Format, list::Order};
 
 const ERROR: fdo::Error = fdo::Error::Failed(String::new());
 
+/// Gets the MPRIS ID of a track, which is made from the hash of its full path.
+fn track_id(track: &tracks::Info) -> TrackId {
+    let path = format!(
+        "/com/talwat/lowfi/track/{:016x}",
+        Cache::hash(&track.full_path)
+    );
+
+    TrackId::try_from(path).unwrap_or(TrackId::NO_TRACK)
+}
+
 /// The actual MPRIS player.
 pub struct Player {
     /// A reference to the [`super::Player`] itself.
@@ -73,7 +89,11 @@ impl RootInterface for Player {
     }
 
     async fn supported_mime_types(&self) -> fdo::Result<Vec<String>> {
-        Ok(vec!["audio/mpeg".to_owned()])
+        Ok(Format::ALL
+            .into_iter()
+            .filter(|x| x.supported())
+            .map(|x| x.mime().to_owned())
+            .collect())
     }
 }
 
@@ -86,7 +106,10 @@ impl PlayerInterface for Player {
     }
 
     async fn previous(&self) -> fdo::Result<()> {
-        Err(ERROR)
+        self.sender
+            .send(Messages::Previous)
+            .await
+            .map_err(|_error| ERROR)
     }
 
     async fn pause(&self) -> fdo::Result<()> {
@@ -114,12 +137,46 @@ impl PlayerInterface for Player {
             .map_err(|_error| ERROR)
     }
 
-    async fn seek(&self, _offset: Time) -> fdo::Result<()> {
-        Err(ERROR)
+    async fn seek(&self, offset: Time) -> fdo::Result<()> {
+        let seconds = Duration::from_micros(offset.as_micros().unsigned_abs()).as_secs_f32();
+        let seconds = if offset.is_negative() {
+            -seconds
+        } else {
+            seconds
+        };
+
+        self.sender
+            .send(Messages::Seek(Seek::By(seconds)))
+            .await
+            .map_err(|_error| ERROR)
     }
 
-    async fn set_position(&self, _track_id: TrackId, _position: Time) -> fdo::Result<()> {
-        Err(ERROR)
+    async fn set_position(&self, id: TrackId, position: Time) -> fdo::Result<()> {
+        let current = self.player.current.load();
+        let Some(current) = current.as_ref() else {
+            return Err(ERROR);
+        };
+
+        // The specification says that positions for other tracks are ignored, since the track could've just changed.
+        if id != track_id(current) {
+            return Ok(());
+        }
+        let duration = current.duration.ok_or(ERROR)?;
+
+        // Positions outside of the track are ignored, as the specification says.
+        let Ok(position) = u64::try_from(position.as_micros()).map(Duration::from_micros) else {
+            return Ok(());
+        };
+        if position > duration {
+            return Ok(());
+        }
+
+        let fraction = position.as_secs_f32() / duration.as_secs_f32();
+
+        self.sender
+            .send(Messages::Seek(Seek::To(fraction)))
+            .await
+            .map_err(|_error| ERROR)
     }
 
     async fn open_uri(&self, _uri: String) -> fdo::Result<()> {
@@ -137,11 +194,30 @@ impl PlayerInterface for Player {
     }
 
     async fn loop_status(&self) -> fdo::Result<LoopStatus> {
-        Err(ERROR)
+        if self.player.repeat.enabled.load(Ordering::Relaxed) {
+            return Ok(LoopStatus::Track);
+        }
+
+        // Every order keeps on playing forever, starting over at the end of the list.
+        Ok(LoopStatus::Playlist)
     }
 
-    async fn set_loop_status(&self, _loop_status: LoopStatus) -> Result<()> {
-        Ok(())
+    async fn set_loop_status(&self, loop_status: LoopStatus) -> Result<()> {
+        if loop_status == LoopStatus::None {
+            return Err(zbus::Error::Failure(
+                "lowfi always starts over at the end of the list".to_owned(),
+            ));
+        }
+
+        let repeat = loop_status == LoopStatus::Track;
+        if repeat == self.player.repeat.enabled.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        self.sender
+            .send(Messages::ToggleRepeat)
+            .await
+            .map_err(|error| zbus::Error::Failure(error.to_string()))
     }
 
     async fn rate(&self) -> fdo::Result<PlaybackRate> {
@@ -154,11 +230,21 @@ impl PlayerInterface for Player {
     }
 
     async fn shuffle(&self) -> fdo::Result<bool> {
-        Ok(true)
+        Ok(self.player.list.order() != Order::Sequential)
     }
 
-    async fn set_shuffle(&self, _shuffle: bool) -> Result<()> {
-        Ok(())
+    async fn set_shuffle(&self, shuffle: bool) -> Result<()> {
+        // Random order is already shuffled, so it's left alone.
+        let order = match (shuffle, self.player.list.order()) {
+            (true, Order::Sequential) => Order::Shuffle,
+            (true, order) => order,
+            (false, _) => Order::Sequential,
+        };
+
+        self.sender
+            .send(Messages::ChangeOrder(order))
+            .await
+            .map_err(|error| zbus::Error::Failure(error.to_string()))
     }
 
     async fn metadata(&self) -> fdo::Result<Metadata> {
@@ -168,11 +254,26 @@ impl PlayerInterface for Player {
             .load()
             .as_ref()
             .map_or_else(Metadata::new, |track| {
+                // A custom name from the list is shown instead of the tags, just like in the UI.
+                let title = match &track.title {
+                    Some(title) if !track.custom_name => title,
+                    _ => &track.display_name,
+                };
+
                 let mut metadata = Metadata::builder()
-                    .title(track.display_name.clone())
-                    .album(self.player.list.name.clone())
+                    .trackid(track_id(track))
+                    .title(title.clone())
+                    .album(
+                        track
+                            .album
+                            .as_deref()
+                            .or(track.list.as_deref())
+                            .unwrap_or_else(|| self.player.list.title()),
+                    )
                     .build();
 
+                metadata.set_artist(track.artist.as_ref().map(|x| [x.clone()]));
+
                 metadata.set_length(
                     track
                         .duration
@@ -197,9 +298,7 @@ impl PlayerInterface for Player {
     }
 
     async fn position(&self) -> fdo::Result<Time> {
-        Ok(Time::from_micros(
-            self.player.sink.get_pos().as_micros() as i64
-        ))
+        Ok(Time::from_micros(self.player.position().as_micros() as i64))
     }
 
     async fn minimum_rate(&self) -> fdo::Result<PlaybackRate> {
@@ -215,7 +314,7 @@ impl PlayerInterface for Player {
     }
 
     async fn can_go_previous(&self) -> fdo::Result<bool> {
-        Ok(false)
+        Ok(!self.player.history.is_empty())
     }
 
     async fn can_play(&self) -> fdo::Result<bool> {
@@ -227,7 +326,12 @@ impl PlayerInterface for Player {
     }
 
     async fn can_seek(&self) -> fdo::Result<bool> {
-        Ok(false)
+        Ok(self
+            .player
+            .current
+            .load()
+            .as_ref()
+            .is_some_and(|x| x.duration.is_some()))
     }
 
     async fn can_control(&self) -> fdo::Result<bool> {
@@ -260,6 +364,17 @@ impl Server {
             .await
     }
 
+    /// Shorthand to emit a `Seeked` signal, after seeking to `position`.
+    pub async fn seeked(&self, position: Duration) -> zbus::Result<()> {
+        self.inner
+            .emit(Signal::Seeked {
+                position: Time::from_micros(
+                    i64::try_from(position.as_micros()).unwrap_or(i64::MAX),
+                ),
+            })
+            .await
+    }
+
     /// Shorthand to get the inner mpris player object.
     pub fn player(&self) -> &Player {
         self.inner.imp()
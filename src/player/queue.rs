@@ -1,20 +1,152 @@
-use std::sync::Arc;
-use tokio::{sync::mpsc::Sender, time::sleep};
+use std::{
+    io,
+    sync::{atomic::Ordering, Arc},
+};
+use tokio::{sync::mpsc::Sender, task};
 
 use crate::{
     messages::Messages,
-    player::{downloader::Downloader, Player, TIMEOUT},
-    tracks,
+    player::{downloader::Downloader, history::Entry, Player},
+    tracks::{
+        self,
+        list::{parse, List, Order},
+        tags::Tags,
+        TrackName,
+    },
 };
 
 impl Player {
-    /// Fetches the next track from the queue, or a random track if the queue is empty.
+    /// Gets the full paths of every track in the buffer, so that they aren't picked again.
+    async fn buffered(&self) -> Vec<String> {
+        self.tracks
+            .read()
+            .await
+            .iter()
+            .map(|x| x.full_path.clone())
+            .collect()
+    }
+
+    /// Throws out every track in the buffer, along with the ones which are still
+    /// being downloaded into it, like when they were picked in a different order.
+    ///
+    /// Any of them which were drawn from the shuffle bag are put back into it,
+    /// apart from the queued track, which is still going to be played.
+    pub async fn reset(&self) {
+        let mut tracks = self.tracks.write().await;
+        self.resets.fetch_add(1, Ordering::Relaxed);
+        tracks.clear();
+        drop(tracks);
+
+        let queued = self.queued.load();
+        let keep: Vec<String> = queued.iter().map(|x| x.full_path.clone()).collect();
+        self.list.restore(&keep);
+    }
+
+    /// Picks the next track to download into the buffer, see [`List::pick`].
+    pub async fn pick(&self) -> Result<parse::Entry, tracks::TrackError> {
+        let buffered = self.buffered().await;
+        self.list.pick(&self.cache, self.offline(), &buffered).await
+    }
+
+    /// Downloads a track which was chosen with [`Player::pick`], while also keeping
+    /// track of network errors so that lowfi can fall back to offline mode.
+    pub async fn download(
+        &self,
+        entry: &parse::Entry,
+    ) -> Result<tracks::QueuedTrack, tracks::TrackError> {
+        let result = self.list.fetch(entry, &self.client, &self.cache).await;
+
+        match &result {
+            Ok(_) => self.record_success(),
+            Err(error) => self.record_failure(error),
+        }
+
+        result
+    }
+
+    /// Starts streaming a random track from the list, keeping track of network errors just like [`Player::download`].
+    pub async fn stream(&self) -> Result<tracks::StreamedTrack, tracks::TrackError> {
+        let buffered = self.buffered().await;
+        let result = self
+            .list
+            .stream(&self.client, &self.cache, self.offline(), &buffered)
+            .await;
+
+        match &result {
+            Ok(_) => self.record_success(),
+            Err(error) => self.record_failure(error),
+        }
+
+        result
+    }
+
+    /// Reports a track which failed to decode to the list, so that it can be quarantined if it keeps failing.
+    async fn check_decoded(
+        &self,
+        full_path: &str,
+        result: Result<tracks::DecodedTrack, tracks::TrackError>,
+    ) -> Result<tracks::DecodedTrack, tracks::TrackError> {
+        if let Err(error) = &result {
+            self.list.report(full_path, error).await;
+        }
+
+        result
+    }
+
+    /// Decodes the track at the front of the buffer, returning [None] if it's empty.
+    ///
+    /// The track is only taken out of the buffer once it's been decoded, so that it isn't lost
+    /// if this is cancelled partway through, like when a track is skipped or the order is changed.
+    async fn decode_next(
+        &self,
+    ) -> Option<(
+        tracks::QueuedTrack,
+        Result<tracks::DecodedTrack, tracks::TrackError>,
+    )> {
+        loop {
+            let track = self.tracks.read().await.front().cloned()?;
+
+            // Decoding has to read the start of the track, so it's done outside of the async runtime.
+            let clone = track.clone();
+            let decoded = match task::spawn_blocking(move || clone.decode()).await {
+                Ok(decoded) => decoded,
+                Err(error) => Err(io::Error::other(error).into()),
+            };
+            let decoded = self.check_decoded(&track.full_path, decoded).await;
+
+            // The buffer might have been changed while decoding, in which case this just starts over.
+            let mut tracks = self.tracks.write().await;
+            let unchanged = tracks
+                .front()
+                .is_some_and(|x| x.full_path == track.full_path);
+            if unchanged {
+                tracks.pop_front();
+            }
+            drop(tracks);
+
+            if unchanged {
+                return Some((track, decoded));
+            }
+        }
+    }
+
+    /// Fetches the next track from the queue, or streams one if the queue is empty,
+    /// unless the list is being played in order, in which case this waits for the queue
+    /// if the downloader is filling it.
     /// This will also set the current track to the fetched track's info.
     async fn fetch(&self) -> Result<tracks::DecodedTrack, tracks::TrackError> {
-        // TODO: Consider replacing this with `unwrap_or_else` when async closures are stablized.
-        let track = self.tracks.write().await.pop_front();
-        let track = if let Some(track) = track {
-            track
+        let mut next = self.decode_next().await;
+
+        // In sequential order, the downloader has already picked the next few tracks,
+        // so streaming another one would play it before them.
+        while next.is_none() && self.list.order() == Order::Sequential && self.buffering() {
+            self.current.store(None);
+            self.downloaded.notified().await;
+            next = self.decode_next().await;
+        }
+
+        let (decoded, track) = if let Some((track, decoded)) = next {
+            (decoded?, Some(track))
         } else {
             // If the queue is completely empty, then fallback to simply getting a new track.
             // This is relevant particularly at the first song.
@@ -23,17 +155,151 @@ impl Player {
             // We're doing it here so that we don't get the "loading" display
             // for only a frame in the other case that the buffer is not empty.
             self.current.store(None);
-            self.list.random(&self.client).await?
-        };
 
-        let decoded = track.decode()?;
+            // The track is streamed, so that it can start playing before it's been fully downloaded.
+            // Decoding waits on the download, so it's done outside of the async runtime.
+            let track = self.stream().await?;
+            let full_path = track.full_path.clone();
+            let decoded = task::block_in_place(|| track.decode());
+            (self.check_decoded(&full_path, decoded).await?, None)
+        };
 
         // Set the current track.
-        self.set_current(decoded.info.clone());
+        self.set_current(decoded.info.clone(), track);
 
         Ok(decoded)
     }
 
+    /// Takes the next track from the buffer as soon as there is one, and then decodes
+    /// & appends it to the sink, so that it's ready to play as soon as the current track ends.
+    ///
+    /// Once it actually starts playing, it's promoted to the current track and a `NewSong` signal is sent to `tx`.
+    pub async fn prepare(
+        player: Arc<Self>,
+        itx: Sender<()>,
+        tx: Sender<Messages>,
+    ) -> eyre::Result<()> {
+        if player.queued.load().is_some() {
+            return Ok(());
+        }
+
+        let (track, queued) = loop {
+            let Some((track, decoded)) = player.decode_next().await else {
+                player.downloaded.notified().await;
+                continue;
+            };
+
+            // Broken tracks are just skipped, since there's no one to report the error to.
+            if let Ok(decoded) = decoded {
+                break (decoded, track);
+            }
+        };
+
+        // Nothing can be cancelled from here on, since the track has already left the buffer.
+        let info = Arc::new(track.info);
+        player.queued.store(Some(Arc::clone(&info)));
+        player.history.queue(Arc::clone(&info), queued);
+
+        // This is a weak reference, since the callback ends up being owned by the player.
+        let weak = Arc::downgrade(&player);
+        let on_start = move || {
+            if weak.upgrade().is_some_and(|player| player.promote(&info)) {
+                let _ = tx.try_send(Messages::NewSong);
+            }
+        };
+
+        player.append(track.data, track.gain, true, Some(Box::new(on_start)));
+
+        // Make space for the downloader to fill back up.
+        Downloader::notify(&itx).await?;
+
+        Ok(())
+    }
+
+    /// Gets an [`Entry`] from the history ready to be played again,
+    /// downloading it again if its data was thrown away.
+    async fn replay(&self, entry: Entry) -> Result<tracks::DecodedTrack, tracks::TrackError> {
+        let track = if let Some(track) = entry.track {
+            track
+        } else {
+            let info = entry.info;
+            let name = if info.custom_name {
+                TrackName::Formatted(info.display_name.clone())
+            } else {
+                TrackName::Raw(info.full_path.clone())
+            };
+
+            // The list might have given the track some of its tags, which
+            // will have been kept in the info, so they're given back here.
+            let tags = Tags {
+                artist: info.artist.clone(),
+                title: info.title.clone(),
+                album: info.album.clone(),
+                gain: None,
+                peak: None,
+            };
+
+            let result = List::redownload(
+                info.full_path.clone(),
+                name,
+                tags,
+                info.list.clone(),
+                &self.client,
+                &self.cache,
+            )
+            .await;
+
+            match &result {
+                Ok(_) => self.record_success(),
+                Err(error) => self.record_failure(error),
+            }
+            let track = result?;
+
+            // The loudness was thrown away with the data, so it's measured again while the track plays.
+            if self.normalize {
+                let track = track.clone();
+                task::spawn_blocking(move || track.analyze());
+            }
+
+            track
+        };
+
+        // Decoding has to read the start of the track, so it's done outside of the async runtime.
+        let clone = track.clone();
+        let decoded = match task::spawn_blocking(move || clone.decode()).await {
+            Ok(decoded) => decoded?,
+            Err(error) => return Err(io::Error::other(error).into()),
+        };
+        self.set_current(decoded.info.clone(), Some(track));
+
+        Ok(decoded)
+    }
+
+    /// Goes back to the previously played track, which is otherwise just like [`Player::next`].
+    ///
+    /// If the track couldn't be played again, then a `TryAgain` signal is sent so
+    /// that a new track is played instead, since there'd be nothing playing otherwise.
+    pub async fn previous(player: Arc<Self>, tx: Sender<Messages>) -> eyre::Result<()> {
+        let Some(entry) = player.history.back() else {
+            return Ok(());
+        };
+
+        player.sink.stop();
+        player.queued.store(None);
+
+        // Serves as an indicator that the track is loading, just like in `fetch`.
+        player.current.store(None);
+
+        if let Ok(track) = player.replay(entry).await {
+            player.append(track.data, track.gain, false, None);
+            tx.send(Messages::NewSong).await?;
+        } else {
+            tx.send(Messages::TryAgain).await?;
+        }
+
+        Ok(())
+    }
+
     /// Gets, decodes, and plays the next track in the queue while also handling the downloader.
     ///
     /// This functions purpose is to be called in the background, so that when the audio server recieves a
@@ -44,17 +310,27 @@ impl Player {
         player: Arc<Self>,
         itx: Sender<()>,
         tx: Sender<Messages>,
-        debug: bool,
     ) -> eyre::Result<()> {
+        // If the next track has already been queued, then just skip to it.
+        if let Some(queued) = player.queued.load_full() {
+            player.sink.skip_one();
+            if player.promote(&queued) {
+                tx.send(Messages::NewSong).await?;
+            }
+
+            return Ok(());
+        }
+
         // Stop the sink.
         player.sink.stop();
+        player.queued.store(None);
 
         let track = player.fetch().await;
 
         match track {
             Ok(track) => {
                 // Start playing the new track.
-                player.sink.append(track.data);
+                player.append(track.data, track.gain, false, None);
 
                 // Notify the background downloader that there's an empty spot
                 // in the buffer.
@@ -64,14 +340,8 @@ impl Player {
                 tx.send(Messages::NewSong).await?;
             }
             Err(error) => {
-                if !error.is_timeout() {
-                    if debug {
-                        panic!("{:?}", error)
-                    }
-
-                    sleep(TIMEOUT).await;
-                }
-
+                // The UI shows why it's waiting, rather than just loading forever.
+                player.retry.wait(&error).await;
                 tx.send(Messages::TryAgain).await?;
             }
         };
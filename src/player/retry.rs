@@ -0,0 +1,132 @@
+//! Contains the [`Retry`] policy, which decides how long to wait
+//! after a track fails before trying to get another one.
+//!
+//! The wait doubles with every failure in a row up to a cap, and is randomized
+//! so that several downloads which fail at once don't all retry at the same moment.
+//! How quickly it grows depends on the [`Cause`], since a timeout is usually just a blip,
+//! while a DNS lookup failing means that there probably isn't a connection at all.
+
+use std::{
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use rand::Rng as _;
+use tokio::time::sleep;
+
+use crate::tracks::TrackError;
+
+/// What made a track fail, which decides how long to wait before trying again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cause {
+    /// The server took too long to respond, or the connection dropped partway through.
+    Timeout,
+
+    /// The server couldn't be reached at all, like when DNS lookups fail,
+    /// or lowfi has fallen back to offline mode & nothing is cached.
+    Connect,
+
+    /// The server responded with a 5xx error.
+    Server,
+
+    /// Something was wrong with the track itself, which the next one probably won't have.
+    Track,
+}
+
+impl Cause {
+    /// Works out the cause of an error.
+    fn new(error: &TrackError) -> Self {
+        match error {
+            TrackError::Request(error) if error.is_connect() => Self::Connect,
+            TrackError::Offline => Self::Connect,
+            TrackError::Timeout | TrackError::Request(_) => Self::Timeout,
+            TrackError::Server(_) => Self::Server,
+            _ => Self::Track,
+        }
+    }
+
+    /// The first wait & the longest wait for this cause.
+    const fn policy(self) -> (Duration, Duration) {
+        match self {
+            Self::Timeout => (Duration::from_secs(1), Duration::from_secs(30)),
+            Self::Connect => (Duration::from_secs(5), Duration::from_mins(2)),
+            Self::Server => (Duration::from_secs(3), Duration::from_mins(1)),
+            Self::Track => (Duration::from_millis(500), Duration::from_secs(5)),
+        }
+    }
+
+    /// A short description which is shown in the UI.
+    const fn label(self) -> &'static str {
+        match self {
+            Self::Timeout => "timed out",
+            Self::Connect => "can't connect",
+            Self::Server => "server error",
+            Self::Track => "track failed",
+        }
+    }
+}
+
+/// The retry policy which is shared by everything that fetches tracks.
+#[derive(Default)]
+pub struct Retry {
+    /// How many times fetching a track has failed in a row.
+    failures: AtomicU32,
+
+    /// When the longest current wait ends & what caused it, if there is one.
+    waiting: Mutex<Option<(Instant, Cause)>>,
+}
+
+impl Retry {
+    /// Gets how long to wait after `failures` failures in a row,
+    /// which is somewhere between half & all of the exponential backoff.
+    fn delay(cause: Cause, failures: u32) -> Duration {
+        let (base, cap) = cause.policy();
+        let delay = base.saturating_mul(2_u32.saturating_pow(failures)).min(cap);
+
+        delay.mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+    }
+
+    /// Waits before the next attempt after `error`, making the following wait longer.
+    pub async fn wait(&self, error: &TrackError) {
+        let cause = Cause::new(error);
+        let delay = Self::delay(cause, self.failures.fetch_add(1, Ordering::Relaxed));
+        let until = Instant::now() + delay;
+
+        {
+            let mut waiting = self.waiting.lock().unwrap();
+            if waiting.is_none_or(|(x, _)| x < until) {
+                *waiting = Some((until, cause));
+            }
+        }
+
+        sleep(delay).await;
+    }
+
+    /// Goes back to the shortest wait, which is done whenever a track is fetched successfully.
+    pub fn reset(&self) {
+        self.failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Whether anything is currently waiting to try again.
+    pub fn waiting(&self) -> bool {
+        self.waiting
+            .lock()
+            .unwrap()
+            .is_some_and(|(until, _)| until > Instant::now())
+    }
+
+    /// Describes the current wait, like `timed out, retrying in 4s`, if there is one.
+    pub fn status(&self) -> Option<String> {
+        let (until, cause) = (*self.waiting.lock().unwrap())?;
+        let remaining = until.checked_duration_since(Instant::now())?;
+
+        Some(format!(
+            "{}, retrying in {}s",
+            cause.label(),
+            remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0)
+        ))
+    }
+}
@@ -103,7 +103,9 @@ impl ComponentWindow {
             volume: 1.0,
             position: Duration::new(0, 0),
             is_bookmarked: false,
+            is_repeating: false,
             borderless,
+            retrying: None,
             custom_data: std::collections::HashMap::new(),
         };
         Self { root: ComponentFactory::create_default_layout(false), borders, borderless, width, out: stdout(), context }
@@ -227,13 +229,16 @@ impl UIManager {
                     display_name: info.display_name.clone(),
                     width: info.width,
                     duration: info.duration,
+                    list: info.list.clone(),
                 })
             });
 
             ctx.volume = self.player.sink.volume();
             ctx.position = current_ref
-                .map_or(Duration::new(0, 0), |_| self.player.sink.get_pos());
+                .map_or(Duration::new(0, 0), |_| self.player.position());
             ctx.is_bookmarked = self.player.bookmarked.load(Ordering::Relaxed);
+            ctx.is_repeating = self.player.repeat.enabled.load(Ordering::Relaxed);
+            ctx.retrying = self.player.retry.status();
         });
 
         let timer = VOLUME_TIMER.load(Ordering::Relaxed);
@@ -264,7 +269,7 @@ impl UIManager {
             | UIEvent::BookmarkChanged => {
                 self.update()?;
             }
-            UIEvent::ProgressUpdate if !self.player.sink.is_paused() && self.player.current_exists() => {
+            UIEvent::ProgressUpdate if (!self.player.sink.is_paused() && self.player.current_exists()) || self.player.retry.waiting() => {
                 self.update()?;
             }
             _ => {}
@@ -76,6 +76,8 @@ pub struct TrackInfo {
     pub display_name: String,
     pub width: usize,
     pub duration: Option<Duration>,
+    /// The list the track came from, if several are being played
+    pub list: Option<String>,
 }
 
 /// Context provided to components for rendering
@@ -92,8 +94,12 @@ pub struct RenderContext {
     pub position: Duration,
     /// Whether track is bookmarked
     pub is_bookmarked: bool,
+    /// Whether the track is being repeated
+    pub is_repeating: bool,
     /// Whether borders should be shown
     pub borderless: bool,
+    /// Why a track is still loading & when it'll be tried again, if it failed
+    pub retrying: Option<String>,
     /// Any custom data
     pub custom_data: std::collections::HashMap<String, String>,
 }
@@ -314,12 +320,19 @@ impl StatusBar {
 
 impl UIComponent for StatusBar {
     fn render(&self, context: &RenderContext) -> String {
+        // "looping" is the same width as "playing", so it can just take its place.
+        let playing = if context.is_repeating {
+            "looping"
+        } else {
+            "playing"
+        };
+
         let (status, track_name, width) = match context.playback_state {
             PlaybackState::Playing => {
                 if let Some(track) = &context.track_info {
-                    ("playing", Some(track.display_name.clone()), track.width)
+                    (playing, Some(track.display_name.clone()), track.width)
                 } else {
-                    ("playing", None, 7)
+                    (playing, None, 7)
                 }
             }
             PlaybackState::Paused => {
@@ -330,7 +343,10 @@ impl UIComponent for StatusBar {
                 }
             }
             PlaybackState::Stopped => ("stopped", None, 7),
-            PlaybackState::Loading => ("loading", None, 7),
+            PlaybackState::Loading => context
+                .retrying
+                .as_ref()
+                .map_or(("loading", None, 7), |x| (x.as_str(), None, 0)),
         };
 
         let bookmark = if self.show_bookmark_indicator && context.is_bookmarked {
@@ -339,14 +355,25 @@ impl UIComponent for StatusBar {
             ""
         };
 
+        let list = match (&context.track_info, &track_name) {
+            (Some(track), Some(_)) => track
+                .list
+                .as_ref()
+                .map_or_else(String::new, |x| format!(" ({x})")),
+            _ => String::new(),
+        };
+
         let full_text = if let Some(name) = track_name.as_ref() {
-            format!("{} {}{}", status, bookmark, name.clone().bold())
+            format!("{} {}{}{}", status, bookmark, name.clone().bold(), list)
         } else {
             status.to_string()
         };
 
-        let text_width =
-            status.len() + bookmark.len() + width + if track_name.is_some() { 1 } else { 0 };
+        let text_width = status.len()
+            + bookmark.len()
+            + width
+            + list.graphemes(true).count()
+            + if track_name.is_some() { 1 } else { 0 };
 
         if text_width > context.width {
             let truncated: String = full_text.graphemes(true).take(context.width + 1).collect();
@@ -5,9 +5,12 @@ use crossterm::event::{self, EventStream, KeyCode, KeyEventKind, KeyModifiers};
 use futures::{FutureExt as _, StreamExt as _};
 use tokio::sync::mpsc::Sender;
 
-use crate::player::{
-    ui::{self, UIError, UIEvent},
-    Messages,
+use crate::{
+    messages::Seek,
+    player::{
+        ui::{self, UIError, UIEvent},
+        Messages,
+    },
 };
 
 /// Starts the listener to recieve input from the terminal for various events.
@@ -24,6 +27,14 @@ pub async fn listen(sender: Sender<Messages>, ui_tx: Sender<UIEvent>) -> eyre::R
         }
 
         let messages = match event.code {
+            // Shift + arrow key seeking.
+            KeyCode::Right if event.modifiers == KeyModifiers::SHIFT => {
+                Messages::Seek(Seek::By(10.0))
+            }
+            KeyCode::Left if event.modifiers == KeyModifiers::SHIFT => {
+                Messages::Seek(Seek::By(-10.0))
+            }
+
             // Arrow key volume controls.
             KeyCode::Up => Messages::ChangeVolume(0.1),
             KeyCode::Right => Messages::ChangeVolume(0.01),
@@ -39,6 +50,9 @@ pub async fn listen(sender: Sender<Messages>, ui_tx: Sender<UIEvent>) -> eyre::R
                 // Skip/Next
                 's' | 'n' | 'l' => Messages::Next,
 
+                // Previous
+                'h' => Messages::Previous,
+
                 // Pause
                 'p' | ' ' => Messages::PlayPause,
 
@@ -49,6 +63,19 @@ pub async fn listen(sender: Sender<Messages>, ui_tx: Sender<UIEvent>) -> eyre::R
                 // Bookmark
                 'b' => Messages::Bookmark,
 
+                // Repeat
+                'r' => Messages::ToggleRepeat,
+
+                // Seeking
+                '.' | '>' => Messages::Seek(Seek::By(10.0)),
+                ',' | '<' => Messages::Seek(Seek::By(-10.0)),
+
+                // Jump to a percentage of the track, with 1 being 10% and so on.
+                '0'..='9' => {
+                    let digit = u8::try_from(character).unwrap_or(b'0') - b'0';
+                    Messages::Seek(Seek::To(f32::from(digit) / 10.0))
+                }
+
                 _ => continue,
             },
             // Media keys
@@ -58,6 +85,7 @@ pub async fn listen(sender: Sender<Messages>, ui_tx: Sender<UIEvent>) -> eyre::R
                 | event::MediaKeyCode::PlayPause => Messages::PlayPause,
                 event::MediaKeyCode::Stop => Messages::Pause,
                 event::MediaKeyCode::TrackNext => Messages::Next,
+                event::MediaKeyCode::TrackPrevious => Messages::Previous,
                 event::MediaKeyCode::LowerVolume => Messages::ChangeVolume(-0.1),
                 event::MediaKeyCode::RaiseVolume => Messages::ChangeVolume(0.1),
                 event::MediaKeyCode::MuteVolume => Messages::ChangeVolume(-1.0),
@@ -72,15 +100,18 @@ pub async fn listen(sender: Sender<Messages>, ui_tx: Sender<UIEvent>) -> eyre::R
                 ui::flash_audio();
                 ui_tx.send(UIEvent::VolumeChanged).await?;
             }
-            Messages::PlayPause | Messages::Pause | Messages::Play => {
+            Messages::PlayPause | Messages::Pause | Messages::Play | Messages::ToggleRepeat => {
                 ui_tx.send(UIEvent::PlaybackStateChanged).await?;
             }
-            Messages::Next => {
+            Messages::Next | Messages::Previous => {
                 ui_tx.send(UIEvent::TrackChanged).await?;
             }
             Messages::Bookmark => {
                 ui_tx.send(UIEvent::BookmarkChanged).await?;
             }
+            Messages::Seek(_) => {
+                ui_tx.send(UIEvent::ProgressUpdate).await?;
+            }
             _ => {}
         }
 
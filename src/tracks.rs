@@ -14,18 +14,42 @@
 //! 1. Track data is decoded.
 //! 2. [`Info`] created from decoded data.
 //! 3. [`Decoded`] made from [`Info`] and the original decoded data.
+//!
+//! If the buffer is empty when a track needs to be played, then the first stage is
+//! skipped in favor of a [`StreamedTrack`], which can be decoded before it's been fully downloaded.
 
-use std::{io::Cursor, time::Duration};
+use std::{
+    io::{self as std_io, Cursor, Read, Seek, SeekFrom},
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
 use bytes::Bytes;
+use format::Format;
 use inflector::Inflector as _;
-use rodio::{Decoder, Source as _};
+use loudness::Gain;
+use reqwest::StatusCode;
+use rodio::{source::SeekError, Decoder, Source};
+use tags::Tags;
 use thiserror::Error;
 use tokio::io;
 use unicode_segmentation::UnicodeSegmentation;
 use url::form_urlencoded;
 
+pub mod bag;
+pub mod cache;
+pub mod format;
 pub mod list;
+pub mod loudness;
+#[cfg(feature = "opus")]
+pub mod opus;
+pub mod quarantine;
+pub mod stream;
+pub mod tags;
+
+/// How many bytes a millisecond of a track is assumed to take up if its duration
+/// can't be read, which is what a 128 kbps MP3 takes.
+const ESTIMATED_BYTES_PER_MILLI: usize = 16;
 
 /// The error type for the track system, which is used to handle errors that occur
 /// while downloading, decoding, or playing tracks.
@@ -48,16 +72,229 @@ pub enum TrackError {
 
     #[error("unable to fetch data")]
     Request(#[from] reqwest::Error),
+
+    #[error("download was cut off")]
+    Interrupted,
+
+    #[error("track doesn't exist anymore ({0})")]
+    Gone(StatusCode),
+
+    #[error("request was rejected ({0})")]
+    Rejected(StatusCode),
+
+    #[error("server error ({0})")]
+    Server(StatusCode),
+
+    #[error("no tracks are available offline")]
+    Offline,
+
+    #[cfg_attr(
+        feature = "opus",
+        allow(
+            dead_code,
+            reason = "every format can be decoded with the opus feature"
+        )
+    )]
+    #[error("{0:?} tracks aren't supported by this build")]
+    Unsupported(Format),
+
+    #[cfg(feature = "opus")]
+    #[error("invalid opus stream")]
+    InvalidOpus,
+
+    #[cfg(feature = "opus")]
+    #[error("unable to decode opus")]
+    Opus(#[from] audiopus::Error),
+
+    #[cfg(feature = "opus")]
+    #[error("unable to read ogg container")]
+    Ogg(#[from] ogg::OggReadError),
 }
 
 impl TrackError {
-    pub fn is_timeout(&self) -> bool {
-        return matches!(self, TrackError::Timeout);
+    /// Whether the error was caused by the network, which
+    /// is used to decide when to fall back to offline mode.
+    pub const fn is_network(&self) -> bool {
+        matches!(
+            self,
+            Self::Timeout | Self::Request(_) | Self::Interrupted | Self::Server(_)
+        )
+    }
+
+    /// Whether the track itself is broken and couldn't be decoded.
+    ///
+    /// Streamed tracks whose download failed are [`TrackError::Interrupted`] instead,
+    /// since the decoder can't tell a broken track apart from one that was cut off.
+    pub const fn is_decode(&self) -> bool {
+        match self {
+            Self::Decode(_) => true,
+            #[cfg(feature = "opus")]
+            Self::InvalidOpus | Self::Opus(_) | Self::Ogg(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Gets the error for an unsuccessful HTTP status, depending on its class,
+    /// or [None] if the status is successful.
+    pub fn from_status(status: StatusCode) -> Option<Self> {
+        if matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) {
+            Some(Self::Gone(status))
+        } else if status.is_client_error() {
+            Some(Self::Rejected(status))
+        } else if status.is_server_error() {
+            Some(Self::Server(status))
+        } else {
+            None
+        }
+    }
+}
+
+/// The raw data of a track which is given to the decoder.
+pub enum TrackData {
+    /// The track has been completely downloaded.
+    Full(Cursor<Bytes>),
+
+    /// The track is still being downloaded.
+    Streamed(stream::Reader),
+}
+
+impl TrackData {
+    /// Creates another handle to the same data, starting from the beginning.
+    ///
+    /// This doesn't copy the data itself, so it's cheap.
+    pub fn reopen(&self) -> Self {
+        match self {
+            Self::Full(cursor) => Self::Full(Cursor::new(cursor.get_ref().clone())),
+            Self::Streamed(reader) => Self::Streamed(reader.reopen()),
+        }
+    }
+}
+
+impl Read for TrackData {
+    fn read(&mut self, buf: &mut [u8]) -> std_io::Result<usize> {
+        match self {
+            Self::Full(cursor) => cursor.read(buf),
+            Self::Streamed(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for TrackData {
+    fn seek(&mut self, pos: SeekFrom) -> std_io::Result<u64> {
+        match self {
+            Self::Full(cursor) => cursor.seek(pos),
+            Self::Streamed(reader) => reader.seek(pos),
+        }
+    }
+}
+
+/// A decoded [`TrackData`], which is usually decoded by [rodio] itself,
+/// except for formats that it doesn't support.
+pub enum DecodedData {
+    /// Decoded by [rodio], which covers MP3, FLAC, Vorbis & WAV.
+    Rodio(Decoder<TrackData>),
+
+    /// Decoded by [`opus::OpusDecoder`].
+    #[cfg(feature = "opus")]
+    Opus(opus::OpusDecoder),
+
+    /// A streamed track, which is decoded ahead of time on its own thread.
+    Prefetched(Box<stream::Prefetch>),
+}
+
+impl DecodedData {
+    /// Detects the format of the data, and then decodes it with the right decoder.
+    ///
+    /// `full_path` is only used to guess the format from the extension
+    /// if it couldn't be detected from the data itself.
+    /// Streamed data is wrapped in a [`stream::Prefetch`], so that waiting on the download
+    /// never holds up the audio thread.
+    pub fn new(mut data: TrackData, full_path: &str) -> eyre::Result<Self, TrackError> {
+        let format = Format::detect(&mut data)?.or_else(|| Format::from_extension(full_path));
+        let streamed = matches!(data, TrackData::Streamed(_));
+
+        let decoded = match format {
+            Some(Format::Mp3) => Self::Rodio(Decoder::new_mp3(data)?),
+            Some(Format::Flac) => Self::Rodio(Decoder::new_flac(data)?),
+            Some(Format::Vorbis) => Self::Rodio(Decoder::new_vorbis(data)?),
+            Some(Format::Wav) => Self::Rodio(Decoder::new_wav(data)?),
+
+            #[cfg(feature = "opus")]
+            Some(Format::Opus) => Self::Opus(opus::OpusDecoder::new(data)?),
+
+            #[cfg(not(feature = "opus"))]
+            Some(Format::Opus) => return Err(TrackError::Unsupported(Format::Opus)),
+
+            None => Self::Rodio(Decoder::new(data)?),
+        };
+
+        if streamed {
+            return Ok(Self::Prefetched(Box::new(stream::Prefetch::new(decoded))));
+        }
+
+        Ok(decoded)
+    }
+}
+
+impl Iterator for DecodedData {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Rodio(decoder) => decoder.next(),
+            #[cfg(feature = "opus")]
+            Self::Opus(decoder) => decoder.next(),
+            Self::Prefetched(decoder) => decoder.next(),
+        }
     }
 }
 
-/// Just a shorthand for a decoded [Bytes].
-pub type DecodedData = Decoder<Cursor<Bytes>>;
+impl Source for DecodedData {
+    fn current_frame_len(&self) -> Option<usize> {
+        match self {
+            Self::Rodio(decoder) => decoder.current_frame_len(),
+            #[cfg(feature = "opus")]
+            Self::Opus(decoder) => decoder.current_frame_len(),
+            Self::Prefetched(decoder) => decoder.current_frame_len(),
+        }
+    }
+
+    fn channels(&self) -> u16 {
+        match self {
+            Self::Rodio(decoder) => decoder.channels(),
+            #[cfg(feature = "opus")]
+            Self::Opus(decoder) => decoder.channels(),
+            Self::Prefetched(decoder) => decoder.channels(),
+        }
+    }
+
+    fn sample_rate(&self) -> u32 {
+        match self {
+            Self::Rodio(decoder) => decoder.sample_rate(),
+            #[cfg(feature = "opus")]
+            Self::Opus(decoder) => decoder.sample_rate(),
+            Self::Prefetched(decoder) => decoder.sample_rate(),
+        }
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        match self {
+            Self::Rodio(decoder) => decoder.total_duration(),
+            #[cfg(feature = "opus")]
+            Self::Opus(decoder) => decoder.total_duration(),
+            Self::Prefetched(decoder) => decoder.total_duration(),
+        }
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        match self {
+            Self::Rodio(decoder) => decoder.try_seek(pos),
+            #[cfg(feature = "opus")]
+            Self::Opus(decoder) => decoder.try_seek(pos),
+            Self::Prefetched(decoder) => decoder.try_seek(pos),
+        }
+    }
+}
 
 /// Specifies a track's name, and specifically,
 /// whether it has already been formatted or if it
@@ -76,6 +313,7 @@ pub enum TrackName {
 /// Tracks which are still waiting in the queue, and can't be played yet.
 ///
 /// This means that only the data & track name are included.
+#[derive(Clone)]
 pub struct QueuedTrack {
     /// Name of the track, which may be raw.
     pub name: TrackName,
@@ -86,14 +324,132 @@ pub struct QueuedTrack {
     /// The raw data of the track, which is not decoded and
     /// therefore much more memory efficient.
     pub data: Bytes,
+
+    /// The tags which the track was given in the list, which
+    /// take priority over the ones embedded in the track.
+    pub tags: Tags,
+
+    /// The name of the list which the track came from, if several were combined.
+    pub list: Option<String>,
+
+    /// The loudness of the track, which is measured
+    /// in the background while it's waiting in the queue.
+    pub loudness: Arc<OnceLock<loudness::Loudness>>,
+
+    /// How long the track is, which is only measured if the
+    /// buffer needs to know how much audio it has.
+    pub duration: Arc<OnceLock<Duration>>,
 }
 
 impl QueuedTrack {
+    /// Creates a new [`QueuedTrack`], whose loudness hasn't been measured yet.
+    pub fn new(
+        name: TrackName,
+        full_path: String,
+        data: Bytes,
+        tags: Tags,
+        list: Option<String>,
+    ) -> Self {
+        Self {
+            name,
+            full_path,
+            data,
+            tags,
+            list,
+            loudness: Arc::default(),
+            duration: Arc::default(),
+        }
+    }
+
+    /// Measures how long the track is, estimating it from the size of the
+    /// track if the decoder doesn't know, which is common for MP3s.
+    ///
+    /// This has to read the start of the track, so it shouldn't be called directly in async code.
+    pub fn measure(&self) {
+        let data = TrackData::Full(Cursor::new(self.data.clone()));
+        let duration = DecodedData::new(data, &self.full_path)
+            .ok()
+            .and_then(|x| x.total_duration())
+            .unwrap_or_else(|| {
+                let millis = self.data.len() / ESTIMATED_BYTES_PER_MILLI;
+                Duration::from_millis(u64::try_from(millis).unwrap_or(u64::MAX))
+            });
+
+        let _ = self.duration.set(duration);
+    }
+
+    /// Measures the loudness of the track, unless it already has a replay gain tag.
+    ///
+    /// This decodes the entire track, so it shouldn't be called directly in async code.
+    pub fn analyze(&self) {
+        let data = TrackData::Full(Cursor::new(self.data.clone()));
+        if self.tags.gain.is_some() || Tags::read(data).gain.is_some() {
+            return;
+        }
+
+        if let Some(loudness) = loudness::measure(self.data.clone(), &self.full_path) {
+            let _ = self.loudness.set(loudness);
+        }
+    }
+
     /// This will actually decode and format the track,
     /// returning a [`DecodedTrack`] which can be played
     /// and also has a duration & formatted name.
     pub fn decode(self) -> eyre::Result<DecodedTrack, TrackError> {
-        DecodedTrack::new(self)
+        DecodedTrack::new(
+            self.name,
+            self.full_path,
+            TrackData::Full(Cursor::new(self.data)),
+            self.tags,
+            self.list,
+            Some(self.loudness),
+        )
+    }
+}
+
+/// A track which is played straight away instead of waiting in the queue,
+/// and so might still be downloading.
+pub struct StreamedTrack {
+    /// Name of the track, which may be raw.
+    pub name: TrackName,
+
+    /// Full downloadable path/url of the track.
+    pub full_path: String,
+
+    /// The data of the track, which may still be arriving.
+    pub data: TrackData,
+
+    /// The tags which the track was given in the list.
+    pub tags: Tags,
+
+    /// The name of the list which the track came from, if several were combined.
+    pub list: Option<String>,
+}
+
+impl StreamedTrack {
+    /// Decodes the track, just like [`QueuedTrack::decode`].
+    ///
+    /// This blocks until enough of the track has been downloaded
+    /// to read its headers, so it shouldn't be called directly in async code.
+    /// If the download fails before then, [`TrackError::Interrupted`] is returned.
+    pub fn decode(self) -> eyre::Result<DecodedTrack, TrackError> {
+        let reader = match &self.data {
+            TrackData::Streamed(reader) => Some(reader.reopen()),
+            TrackData::Full(_) => None,
+        };
+
+        DecodedTrack::new(
+            self.name,
+            self.full_path,
+            self.data,
+            self.tags,
+            self.list,
+            None,
+        )
+        .map_err(|error| match reader {
+            Some(reader) if reader.failed() => TrackError::Interrupted,
+            _ => error,
+        })
     }
 }
 
@@ -110,8 +466,24 @@ pub struct Info {
     pub custom_name: bool,
 
     /// This is a formatted name, so it doesn't include the full path.
+    ///
+    /// It's either the custom name from the list, the artist & title
+    /// from the track's tags, or a name derived from the path, in that order.
     pub display_name: String,
 
+    /// The artist, if the track was tagged with one.
+    pub artist: Option<String>,
+
+    /// The title, if the track was tagged with one.
+    pub title: Option<String>,
+
+    /// The album, if the track was tagged with one.
+    pub album: Option<String>,
+
+    /// The name of the list which the track came from, which
+    /// is only known if several lists have been combined.
+    pub list: Option<String>,
+
     /// This is the *actual* terminal width of the track name, used to make
     /// the UI consistent.
     pub width: usize,
@@ -139,7 +511,7 @@ impl Info {
     fn format_name(name: &str) -> eyre::Result<String, TrackError> {
         let split = name.split('/').last().ok_or(TrackError::InvalidName)?;
 
-        let stripped = split.strip_suffix(".mp3").unwrap_or(split);
+        let stripped = Format::strip_extension(split);
         let formatted = Self::decode_url(stripped)
             .to_lowercase()
             .to_title_case()
@@ -177,15 +549,29 @@ impl Info {
         }
     }
 
-    /// Creates a new [`TrackInfo`] from a possibly raw name & decoded data.
+    /// Creates a new [`TrackInfo`] from a possibly raw name, the track's tags & decoded data.
     pub fn new(
         name: TrackName,
         full_path: String,
+        tags: Tags,
+        list: Option<String>,
         decoded: &DecodedData,
     ) -> eyre::Result<Self, TrackError> {
         let (display_name, custom_name) = match name {
-            TrackName::Raw(raw) => (Self::format_name(&raw)?, false),
             TrackName::Formatted(custom) => (custom, true),
+            TrackName::Raw(raw) => {
+                let title = match &tags.title {
+                    Some(title) => title.clone(),
+                    None => Self::format_name(&raw)?,
+                };
+
+                let name = match &tags.artist {
+                    Some(artist) => format!("{artist} - {title}"),
+                    None => title,
+                };
+
+                (name, false)
+            }
         };
 
         Ok(Self {
@@ -194,6 +580,10 @@ impl Info {
             full_path,
             custom_name,
             display_name,
+            artist: tags.artist,
+            title: tags.title,
+            album: tags.album,
+            list,
         })
     }
 }
@@ -206,15 +596,37 @@ pub struct DecodedTrack {
 
     /// The decoded data, which is able to be played by [rodio].
     pub data: DecodedData,
+
+    /// The volume multiplier which makes the track about as loud as the others.
+    pub gain: Gain,
 }
 
 impl DecodedTrack {
     /// Creates a new track.
-    /// This is equivalent to [`QueuedTrack::decode`].
-    pub fn new(track: QueuedTrack) -> eyre::Result<Self, TrackError> {
-        let data = Decoder::new(Cursor::new(track.data))?;
-        let info = Info::new(track.name, track.full_path, &data)?;
+    /// This is usually called through [`QueuedTrack::decode`] or [`StreamedTrack::decode`].
+    ///
+    /// `tags` are the tags from the list, which are used instead of the ones in the track,
+    /// and `list` is the name of the list that the track came from, if several were combined.
+    /// `loudness` is where the track's loudness is measured to, if it's being measured,
+    /// which is only used if the track doesn't have a replay gain tag.
+    pub fn new(
+        name: TrackName,
+        full_path: String,
+        data: TrackData,
+        tags: Tags,
+        list: Option<String>,
+        loudness: Option<Arc<OnceLock<loudness::Loudness>>>,
+    ) -> eyre::Result<Self, TrackError> {
+        let tags = tags.or(Tags::read(data.reopen()));
+        let gain = match (tags.gain, loudness) {
+            (Some(gain), _) => Gain::Known(loudness::multiplier(gain, tags.peak)),
+            (None, Some(loudness)) => Gain::Measured(loudness),
+            (None, None) => Gain::Known(1.0),
+        };
+
+        let data = DecodedData::new(data, &full_path)?;
+        let info = Info::new(name, full_path, tags, list, &data)?;
 
-        Ok(Self { info, data })
+        Ok(Self { info, data, gain })
     }
 }
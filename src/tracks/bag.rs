@@ -0,0 +1,231 @@
+//! Contains the [`Bag`], which is used to shuffle a list so that
+//! every track is played once before any of them are repeated.
+//!
+//! The tracks which are still left in the bag are saved in `shuffle`
+//! under [`data_dir`] whenever the track changes & when lowfi quits, so that the shuffle carries on
+//! where it left off after lowfi is restarted. Each list has its own file, even when several
+//! are combined, so that a list's shuffle is the same however it's played.
+//!
+//! Tracks which have been drawn but haven't been played yet, like the ones waiting in the buffer,
+//! are saved as if they were still in the bag, since otherwise they'd be skipped until the next shuffle.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+};
+
+use rand::{seq::SliceRandom as _, Rng as _};
+use tokio::fs;
+
+use crate::data_dir;
+
+/// A shuffle bag of the tracks in a list, which are drawn from until
+/// it's empty & then shuffled again.
+pub struct Bag {
+    /// The name of the list that every track came from, along with its full path, which is what's saved.
+    keys: RwLock<Vec<(String, String)>>,
+
+    /// The indices of the tracks which haven't been drawn yet, with the next one at the front.
+    remaining: Mutex<VecDeque<usize>>,
+
+    /// The indices of the tracks which have been drawn, but haven't been played yet, in the order they were drawn.
+    drawn: Mutex<Vec<usize>>,
+}
+
+impl Bag {
+    /// Gets the path where the bag for a list called `name` is saved.
+    pub fn path(name: &str) -> eyre::Result<PathBuf> {
+        Ok(data_dir()?.join("shuffle").join(format!("{name}.txt")))
+    }
+
+    /// Gets the name of every list that the tracks in `keys` came from, without duplicates.
+    fn sources(keys: &[(String, String)]) -> Vec<&str> {
+        let mut sources: Vec<&str> = keys.iter().map(|(source, _)| source.as_str()).collect();
+        sources.sort_unstable();
+        sources.dedup();
+
+        sources
+    }
+
+    /// Loads the bag for some tracks, where `keys` are the names of the lists
+    /// that they came from, along with their full paths.
+    ///
+    /// The bags of each list are mixed together at random, keeping the order within each of them.
+    /// Any saved tracks which aren't in the list anymore are ignored, and a list without
+    /// a saved bag starts with a freshly shuffled one.
+    pub async fn load(keys: Vec<(String, String)>) -> eyre::Result<Self> {
+        let mut bags = Vec::new();
+
+        for source in Self::sources(&keys) {
+            let indices: HashMap<&str, usize> = keys
+                .iter()
+                .enumerate()
+                .filter(|(_, (x, _))| x == source)
+                .map(|(i, (_, x))| (x.as_str(), i))
+                .collect();
+
+            let path = Self::path(source)?;
+            let bag: VecDeque<usize> = if path.exists() {
+                fs::read_to_string(&path)
+                    .await?
+                    .lines()
+                    .filter_map(|x| indices.get(x).copied())
+                    .collect()
+            } else {
+                let mut bag: Vec<usize> = indices.into_values().collect();
+                bag.shuffle(&mut rand::thread_rng());
+                bag.into()
+            };
+
+            bags.push(bag);
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut remaining = VecDeque::new();
+        loop {
+            let total: usize = bags.iter().map(VecDeque::len).sum();
+            if total == 0 {
+                break;
+            }
+
+            let mut index = rng.gen_range(0..total);
+            for bag in &mut bags {
+                if index < bag.len() {
+                    remaining.extend(bag.pop_front());
+                    break;
+                }
+
+                index -= bag.len();
+            }
+        }
+
+        Ok(Self {
+            keys: RwLock::new(keys),
+            remaining: Mutex::new(remaining),
+            drawn: Mutex::default(),
+        })
+    }
+
+    /// Draws the index of the next track out of the bag, refilling it first if it's empty.
+    ///
+    /// Tracks for which `skip` returns true are put back at the bottom of the bag,
+    /// unless every single one has to be skipped, in which case the first one is used anyway.
+    /// The track counts as drawn until it's [`Bag::played`].
+    pub fn draw(&self, skip: impl Fn(usize) -> bool) -> Option<usize> {
+        // The keys are always locked first, so that this can't deadlock with `add`.
+        let count = self.keys.read().unwrap().len();
+        let mut remaining = self.remaining.lock().unwrap();
+        if remaining.is_empty() {
+            let mut indices: Vec<usize> = (0..count).collect();
+            indices.shuffle(&mut rand::thread_rng());
+            remaining.extend(indices);
+        }
+
+        let index = (0..remaining.len())
+            .find_map(|_| {
+                let index = remaining.pop_front()?;
+                if !skip(index) {
+                    return Some(index);
+                }
+
+                remaining.push_back(index);
+                None
+            })
+            .or_else(|| remaining.pop_front())?;
+        drop(remaining);
+
+        self.drawn.lock().unwrap().push(index);
+        Some(index)
+    }
+
+    /// Records that a track which was drawn has started playing, so it's no longer saved with the bag.
+    pub fn played(&self, full_path: &str) {
+        let keys = self.keys.read().unwrap();
+        let mut drawn = self.drawn.lock().unwrap();
+        if let Some(position) = drawn.iter().position(|x| keys[*x].1 == full_path) {
+            drawn.remove(position);
+        }
+    }
+
+    /// Puts every track which was drawn but hasn't been played back at the top of the bag,
+    /// like when the buffer they were drawn into is thrown out.
+    ///
+    /// Tracks with a full path in `keep` are left alone, since they're still going to be played.
+    pub fn restore(&self, keep: &[String]) {
+        let keys = self.keys.read().unwrap();
+        let mut remaining = self.remaining.lock().unwrap();
+        let mut drawn = self.drawn.lock().unwrap();
+
+        let (kept, restored): (Vec<usize>, Vec<usize>) =
+            drawn.drain(..).partition(|x| keep.contains(&keys[*x].1));
+        *drawn = kept;
+        drop(drawn);
+        drop(keys);
+
+        // The bag might have been refilled since, in which case they're already in it.
+        for index in restored.into_iter().rev() {
+            if !remaining.contains(&index) {
+                remaining.push_front(index);
+            }
+        }
+    }
+
+    /// Adds tracks which weren't in the list when the bag was loaded,
+    /// putting them in random places among the tracks which are left.
+    ///
+    /// Just like with [`Bag::load`], `keys` are the names of the lists that they came from & their full paths.
+    pub fn add(&self, keys: Vec<(String, String)>) {
+        let mut all = self.keys.write().unwrap();
+        let mut remaining = self.remaining.lock().unwrap();
+        let mut rng = rand::thread_rng();
+
+        for index in all.len()..all.len() + keys.len() {
+            let position = rng.gen_range(0..=remaining.len());
+            remaining.insert(position, index);
+        }
+        drop(remaining);
+
+        all.extend(keys);
+    }
+
+    /// Saves the tracks which are left in the bag, in the file of the list that each one came from.
+    ///
+    /// Tracks which were drawn but haven't been played are saved at the top.
+    pub async fn save(&self) -> eyre::Result<()> {
+        let contents: HashMap<String, Vec<String>> = {
+            let keys = self.keys.read().unwrap();
+            let mut contents: HashMap<String, Vec<String>> = Self::sources(&keys)
+                .into_iter()
+                .map(|x| (x.to_owned(), Vec::new()))
+                .collect();
+
+            let remaining = self.remaining.lock().unwrap();
+            let drawn = self.drawn.lock().unwrap();
+
+            // The bag might have been refilled since they were drawn, in which case they're already in it.
+            let unplayed = drawn.iter().filter(|x| !remaining.contains(x));
+            for index in unplayed.chain(remaining.iter()) {
+                let (source, key) = &keys[*index];
+                if let Some(bag) = contents.get_mut(source) {
+                    bag.push(key.clone());
+                }
+            }
+            drop(drawn);
+            drop(remaining);
+            drop(keys);
+
+            contents
+        };
+
+        for (source, bag) in contents {
+            let path = Self::path(&source)?;
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&path, bag.join("\n")).await?;
+        }
+
+        Ok(())
+    }
+}
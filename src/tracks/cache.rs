@@ -0,0 +1,185 @@
+//! Contains the [`Cache`], which keeps downloaded tracks on disk so
+//! that they don't have to be fetched over the network again.
+//!
+//! Tracks are stored in `cache` under [`data_dir`], with a filename derived
+//! from the hash of their full path. The modification time of each file is
+//! bumped whenever it's read, so that eviction can remove the least recently
+//! used tracks first once the cache grows beyond its maximum size.
+
+use std::{
+    collections::HashSet,
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use tokio::{fs, io, task};
+
+use crate::data_dir;
+
+/// How old a temporary file has to be before it's assumed to have been left
+/// behind by lowfi being killed while writing it, rather than still being written.
+const STALE: Duration = Duration::from_hours(1);
+
+/// A size-bounded, on-disk cache of raw track data.
+#[derive(Clone)]
+pub struct Cache {
+    /// The directory where all of the cached tracks are stored.
+    dir: PathBuf,
+
+    /// The maximum size of the cache in bytes.
+    /// If this is zero, then the cache is completely disabled.
+    max: u64,
+}
+
+impl Cache {
+    /// Creates the [`Cache`], making sure that the directory exists.
+    ///
+    /// `max` is the maximum size of the cache in bytes.
+    pub async fn new(max: u64) -> eyre::Result<Self> {
+        let dir = data_dir()?.join("cache");
+        if max > 0 {
+            fs::create_dir_all(&dir).await?;
+        }
+
+        Ok(Self { dir, max })
+    }
+
+    /// Whether the cache is enabled, or not.
+    pub const fn enabled(&self) -> bool {
+        self.max > 0
+    }
+
+    /// Hashes a full path using FNV-1a, which is used instead of
+    /// [`std::hash::DefaultHasher`] because it has to be stable across builds.
+    pub fn hash(full_path: &str) -> u64 {
+        full_path.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Gets the path where a track would be stored in the cache.
+    ///
+    /// The file extension of the original track is kept, if there is one.
+    pub fn path(&self, full_path: &str) -> PathBuf {
+        let name = full_path.rsplit('/').next().unwrap_or_default();
+        let extension = name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension)
+            .filter(|x| !x.is_empty() && x.len() <= 4 && x.chars().all(char::is_alphanumeric));
+
+        let hash = Self::hash(full_path);
+        let file = extension.map_or_else(|| format!("{hash:016x}"), |x| format!("{hash:016x}.{x}"));
+
+        self.dir.join(file)
+    }
+
+    /// Checks if a track is currently present in the cache.
+    pub fn contains(&self, full_path: &str) -> bool {
+        self.enabled() && self.path(full_path).exists()
+    }
+
+    /// Lists the paths of every track which is currently cached.
+    ///
+    /// These can be compared against the result of [`Cache::path`].
+    pub async fn cached(&self) -> io::Result<HashSet<PathBuf>> {
+        let mut cached = HashSet::new();
+        if !self.enabled() {
+            return Ok(cached);
+        }
+
+        let mut dir = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            cached.insert(entry.path());
+        }
+
+        Ok(cached)
+    }
+
+    /// Reads a track from the cache, returning [None] if it isn't cached.
+    ///
+    /// This also marks the track as recently used.
+    pub async fn get(&self, full_path: &str) -> Option<Bytes> {
+        if !self.enabled() {
+            return None;
+        }
+
+        let path = self.path(full_path);
+        let data = fs::read(&path).await.ok()?;
+
+        // Failing to bump the modification time only affects the eviction order.
+        // Tokio can't do this itself, so it's done outside of the async runtime.
+        task::spawn_blocking(move || {
+            let _ = std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .and_then(|file| file.set_modified(SystemTime::now()));
+        });
+
+        Some(data.into())
+    }
+
+    /// Writes a track to the cache, and then evicts old tracks if
+    /// the cache has become too large.
+    pub async fn insert(&self, full_path: &str, data: &Bytes) -> io::Result<()> {
+        if !self.enabled() || data.len() as u64 > self.max {
+            return Ok(());
+        }
+
+        // Written to a temporary file first so that an interrupted write
+        // never leaves a truncated track in the cache.
+        let path = self.path(full_path);
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |x| x.subsec_nanos());
+        let temporary = path.with_extension(format!("{nanos}.tmp"));
+
+        fs::write(&temporary, data).await?;
+        fs::rename(&temporary, &path).await?;
+
+        self.evict().await
+    }
+
+    /// Removes the least recently used tracks until the cache is within its maximum size.
+    ///
+    /// Temporary files are left alone & not counted, since they're probably still being written
+    /// by [`Cache::insert`], unless they're older than [`STALE`], in which case they're removed.
+    async fn evict(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        let mut total = 0;
+
+        let mut dir = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = dir.next_entry().await? {
+            let path = entry.path();
+            let metadata = entry.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            if path.extension().is_some_and(|x| x == "tmp") {
+                let age = metadata.modified()?.elapsed().unwrap_or_default();
+                if age > STALE {
+                    fs::remove_file(path).await?;
+                }
+
+                continue;
+            }
+
+            total += metadata.len();
+            entries.push((metadata.modified()?, metadata.len(), path));
+        }
+
+        entries.sort_unstable_by_key(|(modified, _, _)| *modified);
+
+        for (_, len, path) in entries {
+            if total <= self.max {
+                break;
+            }
+
+            fs::remove_file(path).await?;
+            total -= len;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+//! Detection of the container formats which lowfi is able to play.
+
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// An audio format, detected either from a track's contents or its extension.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    /// MPEG Layer III.
+    Mp3,
+
+    /// Free Lossless Audio Codec.
+    Flac,
+
+    /// Vorbis in an Ogg container.
+    Vorbis,
+
+    /// Opus in an Ogg container, which needs the `opus` feature to be played.
+    Opus,
+
+    /// Uncompressed PCM in a RIFF WAVE container.
+    Wav,
+}
+
+impl Format {
+    /// Every format that can be detected.
+    #[cfg_attr(
+        not(feature = "mpris"),
+        allow(dead_code, reason = "the formats are only listed for MPRIS clients")
+    )]
+    pub const ALL: [Self; 5] = [Self::Mp3, Self::Flac, Self::Vorbis, Self::Opus, Self::Wav];
+
+    /// Every file extension which belongs to a known format.
+    const EXTENSIONS: [(&'static str, Self); 7] = [
+        ("mp3", Self::Mp3),
+        ("flac", Self::Flac),
+        ("ogg", Self::Vorbis),
+        ("oga", Self::Vorbis),
+        ("opus", Self::Opus),
+        ("wav", Self::Wav),
+        ("wave", Self::Wav),
+    ];
+
+    /// Whether lowfi was compiled with support for decoding this format.
+    pub const fn supported(self) -> bool {
+        !matches!(self, Self::Opus) || cfg!(feature = "opus")
+    }
+
+    /// The MIME type of the format.
+    #[cfg_attr(
+        not(feature = "mpris"),
+        allow(dead_code, reason = "MIME types are only needed by MPRIS clients")
+    )]
+    pub const fn mime(self) -> &'static str {
+        match self {
+            Self::Mp3 => "audio/mpeg",
+            Self::Flac => "audio/flac",
+            Self::Vorbis => "audio/ogg",
+            Self::Opus => "audio/opus",
+            Self::Wav => "audio/wav",
+        }
+    }
+
+    /// Gets the format from a path or URL, using its extension.
+    pub fn from_extension(path: &str) -> Option<Self> {
+        let (_, extension) = path.rsplit_once('.')?;
+        let extension = extension.to_ascii_lowercase();
+
+        Self::EXTENSIONS
+            .iter()
+            .find(|(x, _)| *x == extension)
+            .map(|(_, format)| *format)
+    }
+
+    /// Removes a known audio extension from the end of a name, if there is one.
+    pub fn strip_extension(name: &str) -> &str {
+        match name.rsplit_once('.') {
+            Some((stem, _)) if Self::from_extension(name).is_some() => stem,
+            _ => name,
+        }
+    }
+
+    /// Detects the format from the first few bytes of a track.
+    pub fn from_header(header: &[u8]) -> Option<Self> {
+        let contains = |needle: &[u8]| header.windows(needle.len()).any(|x| x == needle);
+
+        match header {
+            [b'f', b'L', b'a', b'C', ..] => Some(Self::Flac),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some(Self::Wav),
+            [b'O', b'g', b'g', b'S', ..] if contains(b"OpusHead") => Some(Self::Opus),
+            [b'O', b'g', b'g', b'S', ..] if contains(b"\x01vorbis") => Some(Self::Vorbis),
+            [b'I', b'D', b'3', ..] => Some(Self::Mp3),
+            [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some(Self::Mp3),
+            _ => None,
+        }
+    }
+
+    /// Detects the format of some data by reading the beginning of it,
+    /// and then seeking back to where it was.
+    pub fn detect<R: Read + Seek>(data: &mut R) -> io::Result<Option<Self>> {
+        let start = data.stream_position()?;
+
+        let mut header = Vec::with_capacity(64);
+        data.by_ref().take(64).read_to_end(&mut header)?;
+        data.seek(SeekFrom::Start(start))?;
+
+        Ok(Self::from_header(&header))
+    }
+}
@@ -1,15 +1,63 @@
 //! The module containing all of the logic behind track lists,
 //! as well as obtaining track names & downloading the raw mp3 data.
 
+use std::{
+    collections::{HashMap, HashSet},
+    io::Cursor,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use arc_swap::ArcSwap;
 use bytes::Bytes;
-use eyre::OptionExt as _;
-use rand::Rng as _;
-use reqwest::Client;
-use tokio::fs;
+use clap::ValueEnum;
+use eyre::{eyre, OptionExt as _, WrapErr as _};
+use rand::{
+    distributions::{Distribution as _, WeightedIndex},
+    seq::SliceRandom as _,
+    Rng as _,
+};
+use reqwest::{Client, RequestBuilder, Response};
+use tokio::{fs, task};
+use url::Url;
 
 use crate::{data_dir, tracks::TrackError};
 
-use super::QueuedTrack;
+use super::{
+    bag::Bag, cache::Cache, quarantine::Quarantine, stream, tags::Tags, QueuedTrack, StreamedTrack,
+    TrackData, TrackName,
+};
+use directory::Directory;
+use parse::{Entry, Header, ParseError};
+
+pub mod directory;
+pub mod parse;
+pub mod playlist;
+pub mod remote;
+
+/// How many random tracks to try before only picking from the ones which aren't skipped.
+const ATTEMPTS: usize = 16;
+
+/// How long a streamed track has to finish downloading before giving up.
+const STREAM_TIMEOUT: Duration = Duration::from_mins(10);
+
+/// The order in which the tracks of a list are played.
+#[derive(ValueEnum, PartialEq, Eq, Debug, Default, Clone, Copy)]
+pub enum Order {
+    /// In the same order as the list, starting over once the end is reached.
+    Sequential,
+
+    /// Completely at random, so tracks can be repeated.
+    #[default]
+    Random,
+
+    /// Shuffled, so that every track is played once before any are repeated.
+    Shuffle,
+}
 
 /// Represents a list of tracks that can be played.
 ///
@@ -17,132 +65,805 @@ use super::QueuedTrack;
 #[derive(Clone)]
 pub struct List {
     /// The "name" of the list, usually derived from a filename.
-    #[allow(dead_code, reason = "this code may not be dead depending on features")]
     pub name: String,
 
-    /// Just the raw file, but seperated by `/n` (newlines).
-    /// `lines[0]` is the base, with the rest being tracks.
-    lines: Vec<String>,
+    /// What the list's shuffle bag, sequential position & quarantine are saved under, see [`List::id`].
+    pub id: String,
+
+    /// The metadata from the top of the list.
+    pub header: Header,
+
+    /// The tracks themselves, which can be swapped out while
+    /// playing if tracks are added to a [`Directory`].
+    tracks: Arc<ArcSwap<Tracks>>,
+
+    /// The directory that the list was built from, if it was.
+    directory: Option<Arc<Directory>>,
+
+    /// The order that tracks are being played in.
+    order: Arc<Mutex<Order>>,
+
+    /// The index of the next track to be played in sequential order, not counting the base.
+    position: Arc<AtomicUsize>,
+
+    /// The shuffle bag which tracks are drawn from when shuffling, if it's been loaded.
+    bag: Option<Arc<Bag>>,
+
+    /// The tracks which are broken & shouldn't be picked, once they've been loaded.
+    /// Each list that was combined has its own, which is keyed by the list's name.
+    quarantines: HashMap<String, Arc<Quarantine>>,
+}
+
+/// Every track in a [List], along with how likely they are to be picked.
+pub struct Tracks {
+    /// Every track in the list, in the same order as the file.
+    pub entries: Vec<Entry>,
+
+    /// How likely each track is to be picked compared to the others,
+    /// which takes both the `weight` attributes and the ratios of combined lists into account.
+    weights: Vec<f64>,
+
+    /// The distribution used to pick random tracks, which is
+    /// [None] if every track has the same weight.
+    distribution: Option<WeightedIndex<f64>>,
+}
+
+impl Tracks {
+    /// Creates a new [`Tracks`], where `weighted` is false if every track has the same weight.
+    fn new(entries: Vec<Entry>, weights: Vec<f64>, weighted: bool) -> Self {
+        Self {
+            distribution: weighted
+                .then(|| WeightedIndex::new(&weights).ok())
+                .flatten(),
+            entries,
+            weights,
+        }
+    }
+
+    /// Gets a random track, taking the weights of tracks into account.
+    ///
+    /// Tracks for which `skip` returns true are avoided, unless every track has to be skipped.
+    fn random(&self, skip: impl Fn(&Entry) -> bool) -> &Entry {
+        let mut rng = rand::thread_rng();
+        let mut random = || match &self.distribution {
+            Some(weights) => &self.entries[weights.sample(&mut rng)],
+            None => &self.entries[rng.gen_range(0..self.entries.len())],
+        };
+
+        for _ in 0..ATTEMPTS {
+            let entry = random();
+            if !skip(entry) {
+                return entry;
+            }
+        }
+
+        // Most of the tracks must be skipped, so it's quicker to only pick from the ones which aren't.
+        let available: Vec<(&Entry, f64)> = self
+            .entries
+            .iter()
+            .zip(self.weights.iter().copied())
+            .filter(|(entry, _)| !skip(entry))
+            .collect();
+
+        available
+            .choose_weighted(&mut rand::thread_rng(), |x| x.1)
+            .map_or_else(|_| random(), |x| x.0)
+    }
+
+    /// Gets a random track which can be played without the network,
+    /// meaning that it's either a local file or has already been cached.
+    ///
+    /// `cached` should be the result of [`Cache::cached`], and tracks for which `skip` returns true are ignored.
+    fn offline(
+        &self,
+        cache: &Cache,
+        cached: &HashSet<PathBuf>,
+        skip: impl Fn(&Entry) -> bool,
+    ) -> Option<&Entry> {
+        let available: Vec<(&Entry, f64)> = self
+            .entries
+            .iter()
+            .zip(self.weights.iter().copied())
+            .filter(|(entry, _)| {
+                let full_path = entry.full_path();
+                let local =
+                    full_path.starts_with("file://") || cached.contains(&cache.path(&full_path));
+
+                local && !skip(entry)
+            })
+            .collect();
+
+        available
+            .choose_weighted(&mut rand::thread_rng(), |x| x.1)
+            .ok()
+            .map(|x| x.0)
+    }
 }
 
 impl List {
-    /// Gets the base URL of the [List].
-    pub fn base(&self) -> &str {
-        self.lines[0].trim()
-    }
-
-    /// Gets the path of a random track.
-    ///
-    /// The second value in the tuple specifies whether the
-    /// track has a custom display name.
-    fn random_path(&self) -> (String, Option<String>) {
-        // We're getting from 1 here, since the base is at `self.lines[0]`.
-        //
-        // We're also not pre-trimming `self.lines` into `base` & `tracks` due to
-        // how rust vectors work, since it is slower to drain only a single element from
-        // the start, so it's faster to just keep it in & work around it.
-        let random = rand::thread_rng().gen_range(1..self.lines.len());
-        let line = self.lines[random].clone();
-
-        if let Some((first, second)) = line.split_once('!') {
-            (first.to_owned(), Some(second.to_owned()))
-        } else {
-            (line, None)
+    /// Gets every track in the list.
+    pub fn tracks(&self) -> Arc<Tracks> {
+        self.tracks.load_full()
+    }
+
+    /// Gets the human readable name of the list, which is either from `#name:` or just the normal name.
+    pub fn title(&self) -> &str {
+        self.header.name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Gets the ID of a list called `name` which was loaded from `source`, which is
+    /// either a URL or a canonical path.
+    ///
+    /// Installed lists are just saved under their name, but any other list also has
+    /// a hash of where it came from, so that it doesn't share its state with an
+    /// installed list, or another list, which happens to have the same name.
+    pub fn id(name: &str, source: &str) -> String {
+        format!("{name}-{:016x}", Cache::hash(source))
+    }
+
+    /// Gets the ID of the list that a track came from, which is this one unless several were combined.
+    /// This is what the track's quarantine & shuffle bag are saved under.
+    fn source<'a>(&'a self, entry: &'a Entry) -> &'a str {
+        entry.source.as_deref().unwrap_or(&self.id)
+    }
+
+    /// Gets what a track is saved as in the shuffle [`Bag`], which is the list
+    /// that it came from along with its full path.
+    fn key(&self, entry: &Entry) -> (String, String) {
+        (self.source(entry).to_owned(), entry.full_path())
+    }
+
+    /// Whether a track has been quarantined, and so shouldn't be picked.
+    fn quarantined(&self, entry: &Entry) -> bool {
+        self.quarantines
+            .get(self.source(entry))
+            .is_some_and(|x| x.contains(&entry.full_path()))
+    }
+
+    /// Records an error from a track, so that it's quarantined if it's persistently broken.
+    /// The track is quarantined in the list that it came from, even if several were combined.
+    ///
+    /// See [`Quarantine::report`] for which errors actually quarantine a track.
+    pub async fn report(&self, full_path: &str, error: &TrackError) {
+        let tracks = self.tracks();
+        let source = tracks
+            .entries
+            .iter()
+            .find(|x| x.full_path() == full_path)
+            .map_or(self.id.as_str(), |x| self.source(x));
+
+        if let Some(quarantine) = self.quarantines.get(source) {
+            quarantine.report(full_path, error).await;
         }
     }
 
-    /// Downloads a raw track, but doesn't decode it.
-    async fn download(&self, track: &str, client: &Client) -> Result<(Bytes, String), TrackError> {
-        // If the track has a protocol, then we should ignore the base for it.
-        let full_path = if track.contains("://") {
-            track.to_owned()
-        } else {
-            format!("{}{}", self.base(), track)
+    /// Records that a track has started playing, so that it's taken out of the shuffle bag for good.
+    ///
+    /// See [`Bag::played`] for more details.
+    pub fn played(&self, full_path: &str) {
+        if let Some(bag) = &self.bag {
+            bag.played(full_path);
+        }
+    }
+
+    /// Puts the tracks which were drawn from the shuffle bag but never played back into it,
+    /// except for the ones with a full path in `keep`.
+    ///
+    /// See [`Bag::restore`] for more details.
+    pub fn restore(&self, keep: &[String]) {
+        if let Some(bag) = &self.bag {
+            bag.restore(keep);
+        }
+    }
+
+    /// Gets the next track in the list, going back to the first once the end is reached.
+    ///
+    /// Quarantined tracks are skipped over, unless every track is quarantined.
+    fn sequential_entry<'a>(&self, tracks: &'a Tracks) -> &'a Entry {
+        let next = || {
+            let index = self.position.fetch_add(1, Ordering::Relaxed) % tracks.entries.len();
+            &tracks.entries[index]
         };
 
-        let data: Bytes = if let Some(x) = full_path.strip_prefix("file://") {
-            let path = if x.starts_with("~") {
-                let home_path = dirs::home_dir().ok_or(TrackError::InvalidPath)?;
-                let home = home_path.to_str().ok_or(TrackError::InvalidPath)?;
+        let first = next();
+        if !self.quarantined(first) {
+            return first;
+        }
+
+        (1..tracks.entries.len())
+            .map(|_| next())
+            .find(|x| !self.quarantined(x))
+            .unwrap_or(first)
+    }
 
-                x.replace("~", home)
+    /// Draws the next track from the shuffle bag, if it's been loaded.
+    ///
+    /// Tracks with a full path in `exclude`, or which are quarantined, are avoided if possible.
+    fn shuffled_entry<'a>(&self, tracks: &'a Tracks, exclude: &[String]) -> Option<&'a Entry> {
+        let bag = self.bag.as_ref()?;
+        let index = bag.draw(|x| {
+            let entry = &tracks.entries[x];
+            exclude.contains(&entry.full_path()) || self.quarantined(entry)
+        })?;
+
+        tracks.entries.get(index)
+    }
+
+    /// Sends a request, converting timeouts into [`TrackError::Timeout`]
+    /// and unsuccessful statuses into an error depending on their class.
+    async fn send(request: RequestBuilder) -> Result<Response, TrackError> {
+        let response = request.send().await.map_err(|error| {
+            if error.is_timeout() {
+                TrackError::Timeout
             } else {
-                x.to_owned()
-            };
+                TrackError::Request(error)
+            }
+        })?;
+
+        TrackError::from_status(response.status()).map_or(Ok(response), Err)
+    }
 
-            let result = tokio::fs::read(path).await?;
+    /// Gets where a local track actually is from the part of its full path after `file://`,
+    /// which means expanding `~` into the home directory.
+    pub fn local_path(path: &str) -> Result<String, TrackError> {
+        if !path.starts_with('~') {
+            return Ok(path.to_owned());
+        }
+
+        let home_path = dirs::home_dir().ok_or(TrackError::InvalidPath)?;
+        let home = home_path.to_str().ok_or(TrackError::InvalidPath)?;
+
+        Ok(path.replace('~', home))
+    }
+
+    /// Downloads a raw track from its full path, but doesn't decode it.
+    ///
+    /// Tracks which are already in the [`Cache`] won't be fetched again,
+    /// and newly fetched tracks are added to it.
+    async fn download(
+        full_path: &str,
+        client: &Client,
+        cache: &Cache,
+    ) -> Result<Bytes, TrackError> {
+        let data: Bytes = if let Some(x) = full_path.strip_prefix("file://") {
+            let result = tokio::fs::read(Self::local_path(x)?).await?;
             result.into()
+        } else if let Some(data) = cache.get(full_path).await {
+            data
         } else {
-            let response = match client.get(full_path.clone()).send().await {
-                Ok(x) => Ok(x),
-                Err(x) => {
-                    if x.is_timeout() {
-                        Err(TrackError::Timeout)
-                    } else {
-                        Err(TrackError::Request(x))
-                    }
-                }
-            }?;
-            response.bytes().await?
+            let response = Self::send(client.get(full_path)).await?;
+            let data = response.bytes().await?;
+
+            // A track that couldn't be cached can still be played.
+            let _ = cache.insert(full_path, &data).await;
+
+            data
         };
 
-        Ok((data, full_path))
+        Ok(data)
     }
 
-    /// Fetches and downloads a random track from the [List].
+    /// Chooses the next track from the [List], depending on its [`Order`].
     ///
-    /// The Result's error is a bool, which is true if a timeout error occured,
-    /// and false otherwise. This tells lowfi if it shouldn't wait to try again.
-    pub async fn random(&self, client: &Client) -> Result<QueuedTrack, TrackError> {
-        let (path, custom_name) = self.random_path();
-        let (data, full_path) = self.download(&path, client).await?;
+    /// If `offline` is true, then only local & cached tracks will be chosen,
+    /// and [`TrackError::Offline`] is returned if there aren't any.
+    /// `exclude` has the full paths of tracks which shouldn't be drawn from the shuffle bag,
+    /// usually because they're already waiting in the buffer.
+    pub async fn pick(
+        &self,
+        cache: &Cache,
+        offline: bool,
+        exclude: &[String],
+    ) -> Result<Entry, TrackError> {
+        self.refresh().await;
+        let tracks = self.tracks();
+        let skip = |entry: &Entry| self.quarantined(entry);
+
+        let entry = if offline {
+            let cached = cache.cached().await?;
+            tracks
+                .offline(cache, &cached, skip)
+                .ok_or(TrackError::Offline)?
+        } else {
+            match self.order() {
+                Order::Sequential => self.sequential_entry(&tracks),
+                Order::Random => tracks.random(skip),
+                Order::Shuffle => self
+                    .shuffled_entry(&tracks, exclude)
+                    .unwrap_or_else(|| tracks.random(skip)),
+            }
+        };
+
+        Ok(entry.clone())
+    }
+
+    /// Adds any tracks which have appeared in the [`Directory`] since it was last scanned,
+    /// which only does anything if the list was built from a directory.
+    async fn refresh(&self) {
+        let Some(directory) = &self.directory else {
+            return;
+        };
+
+        let tracks = self.tracks();
+        let added = directory.rescan(&tracks.entries).await;
+        if added.is_empty() {
+            return;
+        }
+
+        if let Some(bag) = &self.bag {
+            bag.add(added.iter().map(|x| self.key(x)).collect());
+        }
+
+        let mut entries = tracks.entries.clone();
+        entries.extend(added);
+        let weights = vec![1.0; entries.len()];
+        self.tracks
+            .store(Arc::new(Tracks::new(entries, weights, false)));
+    }
+
+    /// Downloads a track which was chosen with [`List::pick`].
+    ///
+    /// Picking & downloading are separate so that several tracks can be downloaded
+    /// at once, while still ending up in the buffer in the order they were picked.
+    pub async fn fetch(
+        &self,
+        entry: &Entry,
+        client: &Client,
+        cache: &Cache,
+    ) -> Result<QueuedTrack, TrackError> {
+        let full_path = entry.full_path();
+        let data = match Self::download(&full_path, client, cache).await {
+            Ok(data) => data,
+            Err(error) => {
+                self.report(&full_path, &error).await;
+                return Err(error);
+            }
+        };
+
+        Ok(QueuedTrack::new(
+            entry.track_name(),
+            full_path,
+            data,
+            entry.tags(),
+            entry.list.clone(),
+        ))
+    }
+
+    /// Downloads a track which has already been played once before, like
+    /// when going back to it after its data has been thrown away.
+    ///
+    /// `tags` are the tags that the track was given by the list, if any,
+    /// and `list` is the name of the list it came from, if several were combined.
+    pub async fn redownload(
+        full_path: String,
+        name: TrackName,
+        tags: Tags,
+        list: Option<String>,
+        client: &Client,
+        cache: &Cache,
+    ) -> Result<QueuedTrack, TrackError> {
+        let data = Self::download(&full_path, client, cache).await?;
+
+        Ok(QueuedTrack::new(name, full_path, data, tags, list))
+    }
 
-        let name = custom_name.map_or(super::TrackName::Raw(path.clone()), |formatted| {
-            super::TrackName::Formatted(formatted)
+    /// Starts streaming a random track from the [List], which means that it can be
+    /// decoded & played as soon as the beginning of the track has been downloaded.
+    ///
+    /// The rest of the track is downloaded in the background, and then added to
+    /// the [`Cache`] once it's finished. Local & cached tracks are just read normally.
+    pub async fn stream(
+        &self,
+        client: &Client,
+        cache: &Cache,
+        offline: bool,
+        exclude: &[String],
+    ) -> Result<StreamedTrack, TrackError> {
+        let entry = self.pick(cache, offline, exclude).await?;
+        let (name, tags, list) = (entry.track_name(), entry.tags(), entry.list.clone());
+        let full_path = entry.full_path();
+
+        if full_path.starts_with("file://") || cache.contains(&full_path) {
+            let data = match Self::download(&full_path, client, cache).await {
+                Ok(data) => data,
+                Err(error) => {
+                    self.report(&full_path, &error).await;
+                    return Err(error);
+                }
+            };
+
+            return Ok(StreamedTrack {
+                name,
+                full_path,
+                data: TrackData::Full(Cursor::new(data)),
+                tags,
+                list,
+            });
+        }
+
+        // The client's timeout covers the whole body, which isn't
+        // appropriate when the body is being played as it arrives.
+        let request = client.get(full_path.clone()).timeout(STREAM_TIMEOUT);
+        let mut response = match Self::send(request).await {
+            Ok(response) => response,
+            Err(error) => {
+                self.report(&full_path, &error).await;
+                return Err(error);
+            }
+        };
+        let (writer, reader) = stream::channel(response.content_length());
+
+        let cache = cache.clone();
+        let key = full_path.clone();
+        task::spawn(async move {
+            loop {
+                match response.chunk().await {
+                    // There's no point in continuing if the track was already skipped.
+                    Ok(Some(_)) if writer.abandoned() => return,
+                    Ok(Some(chunk)) => writer.push(&chunk),
+                    Ok(None) => break,
+
+                    // Dropping the writer lets the reader know that the download failed.
+                    Err(_) => return,
+                }
+            }
+
+            let data = writer.finish();
+            let _ = cache.insert(&key, &data).await;
         });
 
-        Ok(QueuedTrack {
+        Ok(StreamedTrack {
             name,
-            data,
             full_path,
+            data: TrackData::Streamed(reader),
+            tags,
+            list,
         })
     }
 
     /// Parses text into a [List].
-    pub fn new(name: &str, text: &str) -> Self {
-        let lines: Vec<String> = text
-            .trim()
-            .lines()
-            .map(|x| x.trim_end().to_owned())
-            .collect();
+    ///
+    /// See [`parse::parse`] for the details of how this works.
+    pub fn new(name: &str, text: &str) -> Result<Self, ParseError> {
+        let (header, entries) = parse::parse(text)?;
+
+        Ok(Self::from_entries(name, header, entries))
+    }
+
+    /// Creates a [List] out of tracks which have already been parsed.
+    fn from_entries(name: &str, header: Header, entries: Vec<Entry>) -> Self {
+        let weights: Vec<f64> = entries.iter().map(Entry::weight).collect();
+
+        // Lists where every track has the default weight are just picked from uniformly.
+        let weighted = entries.iter().any(|x| x.attribute("weight").is_some());
 
         Self {
-            lines,
             name: name.to_owned(),
+            id: name.to_owned(),
+            header,
+            tracks: Arc::new(ArcSwap::from_pointee(Tracks::new(
+                entries, weights, weighted,
+            ))),
+            directory: None,
+            order: Arc::default(),
+            position: Arc::default(),
+            bag: None,
+            quarantines: HashMap::new(),
         }
     }
 
-    /// Reads a [List] from the filesystem using the CLI argument provided.
-    pub async fn load(tracks: Option<&String>) -> eyre::Result<Self> {
-        if let Some(arg) = tracks {
-            // Check if the track is in ~/.local/share/lowfi, in which case we'll load that.
-            let name = data_dir()?.join(format!("{arg}.txt"));
+    /// Builds a [List] out of every track in a directory, which is kept
+    /// up to date with any tracks that are added to it while playing.
+    ///
+    /// See [`Directory`] for more details.
+    async fn from_directory(path: &Path) -> eyre::Result<Self> {
+        let (directory, entries) = Directory::load(path).await?;
+        let name = path
+            .file_name()
+            .and_then(|x| x.to_str())
+            .ok_or_eyre("invalid track directory")?;
 
-            let name = if name.exists() { name } else { arg.into() };
+        Ok(Self {
+            id: Self::id(name, &directory.path().to_string_lossy()),
+            directory: Some(directory),
+            ..Self::from_entries(name, Header::default(), entries)
+        })
+    }
 
-            let raw = fs::read_to_string(name.clone()).await?;
+    /// Combines several lists into one, where each list is paired with its ratio.
+    ///
+    /// When playing randomly, the ratios decide how often a track is picked from each list,
+    /// no matter how many tracks are in them. Sequential playback just goes through each list in turn.
+    ///
+    /// Tracks which are added to a directory aren't noticed once it's been combined with other lists.
+    fn combine(lists: Vec<(Self, f64)>) -> Self {
+        let name = lists
+            .iter()
+            .map(|(list, _)| list.name.as_str())
+            .collect::<Vec<_>>()
+            .join("_");
+        let id = lists
+            .iter()
+            .map(|(list, _)| list.id.as_str())
+            .collect::<Vec<_>>()
+            .join("_");
 
-            let name = name
-                .file_stem()
-                .and_then(|x| x.to_str())
-                .ok_or_eyre("invalid track path")?;
+        let mut entries = Vec::new();
+        let mut weights = Vec::new();
+        for (list, ratio) in lists {
+            let title = list.title();
+            let tracks = list.tracks();
+            let total: f64 = tracks.weights.iter().sum();
 
-            Ok(Self::new(name, &raw))
-        } else {
-            Ok(Self::new(
-                "lofigirl",
-                include_str!("../../data/lofigirl.txt"),
-            ))
+            weights.extend(tracks.weights.iter().map(|x| ratio * x / total));
+            entries.extend(tracks.entries.iter().map(|entry| {
+                let mut entry = entry.clone();
+                entry.list = Some(title.to_owned());
+                entry.source = Some(list.id.clone());
+                entry
+            }));
         }
+
+        Self {
+            name,
+            id,
+            header: Header::default(),
+            tracks: Arc::new(ArcSwap::from_pointee(Tracks::new(entries, weights, true))),
+            directory: None,
+            order: Arc::default(),
+            position: Arc::default(),
+            bag: None,
+            quarantines: HashMap::new(),
+        }
+    }
+
+    /// Gets the [`Order`] that tracks are currently being played in.
+    pub fn order(&self) -> Order {
+        *self.order.lock().unwrap()
+    }
+
+    /// Changes the [`Order`] that tracks are played in.
+    pub fn set_order(&self, order: Order) {
+        *self.order.lock().unwrap() = order;
+    }
+
+    /// Gets the path where the sequential position of a list is saved, which is right
+    /// next to its shuffle bag. `id` is the list's [`List::id`].
+    pub fn position_path(id: &str) -> eyre::Result<PathBuf> {
+        Ok(data_dir()?.join("shuffle").join(format!("{id}.position")))
+    }
+
+    /// Prepares the list to be played in `order`.
+    ///
+    /// This also loads the shuffle bag, so that the order can be
+    /// switched to [`Order::Shuffle`] at any point afterwards,
+    /// as well as the [`Quarantine`] of broken tracks for every list that
+    /// the tracks came from & the saved sequential position.
+    pub async fn start(&mut self, order: Order) -> eyre::Result<()> {
+        let keys: Vec<(String, String)> =
+            self.tracks().entries.iter().map(|x| self.key(x)).collect();
+
+        // The position is saved as the next track's path, so that it survives the list being edited.
+        let path = Self::position_path(&self.id)?;
+        if path.exists() {
+            let next = fs::read_to_string(&path).await?;
+            if let Some(index) = keys.iter().position(|(_, x)| x == next.trim()) {
+                self.position.store(index, Ordering::Relaxed);
+            }
+        }
+
+        let bag = Bag::load(keys).await?;
+        self.bag = Some(Arc::new(bag));
+
+        let mut sources: Vec<String> = self
+            .tracks()
+            .entries
+            .iter()
+            .map(|x| self.source(x).to_owned())
+            .collect();
+        sources.sort_unstable();
+        sources.dedup();
+
+        for source in sources {
+            let quarantine = Quarantine::load(&source).await?;
+            self.quarantines.insert(source, Arc::new(quarantine));
+        }
+        self.set_order(order);
+
+        Ok(())
+    }
+
+    /// Saves the shuffle bag, along with where the list is up to in sequential order, which is
+    /// the track after `current`, so that both carry on from there the next time lowfi is started.
+    ///
+    /// The position is only saved while playing in [`Order::Sequential`], since otherwise the
+    /// current track has nothing to do with it.
+    pub async fn save(&self, current: Option<&str>) -> eyre::Result<()> {
+        if let Some(bag) = &self.bag {
+            bag.save().await?;
+        }
+
+        if self.order() != Order::Sequential {
+            return Ok(());
+        }
+
+        let tracks = self.tracks();
+        let Some(index) = current
+            .and_then(|current| tracks.entries.iter().position(|x| x.full_path() == current))
+        else {
+            return Ok(());
+        };
+
+        let next = &tracks.entries[(index + 1) % tracks.entries.len()];
+        let path = Self::position_path(&self.id)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(path, next.full_path()).await?;
+
+        Ok(())
+    }
+
+    /// Splits the ratio off of a list argument like `chillhop:2`, which defaults to one.
+    ///
+    /// Anything after the last `:` which isn't a positive number is left alone,
+    /// so that paths with a `:` in them still work.
+    fn split_ratio(arg: &str) -> (&str, f64) {
+        arg.rsplit_once(':')
+            .and_then(|(name, ratio)| {
+                let ratio: f64 = ratio.parse().ok()?;
+                (ratio.is_finite() && ratio > 0.0).then_some((name, ratio))
+            })
+            .unwrap_or((arg, 1.0))
+    }
+
+    /// Gets the path that a list called `name` is installed at in the data directory,
+    /// which is where [`List::load`] looks first.
+    pub fn installed(name: &str) -> eyre::Result<PathBuf> {
+        Ok(data_dir()?.join(format!("{name}.txt")))
+    }
+
+    /// Gets the default list, which is embedded into lowfi itself.
+    fn lofigirl() -> Result<Self, ParseError> {
+        Self::new("lofigirl", include_str!("../../data/lofigirl.txt"))
+    }
+
+    /// Reads a single [List], which is either the name of one in the data directory,
+    /// a path to one, a directory of tracks, or a URL which is fetched with [`remote::load`].
+    ///
+    /// Paths can also point to a playlist from another player, see [`playlist`].
+    async fn load_one(arg: &str, offline: bool) -> eyre::Result<Self> {
+        if Path::new(arg).is_dir() {
+            return Self::from_directory(Path::new(arg)).await;
+        }
+
+        if remote::is_remote(arg) {
+            let (name, raw) = remote::load(arg, offline)
+                .await
+                .wrap_err_with(|| format!("unable to fetch track list {arg}"))?;
+
+            return Ok(Self {
+                id: Self::id(&name, arg),
+                ..Self::from_text(&name, &raw, &Url::parse(arg)?)?
+            });
+        }
+
+        // Check if the track is in ~/.local/share/lowfi, in which case we'll load that.
+        let path = Self::installed(arg)?;
+        let installed = path.exists();
+
+        let path = if installed { path } else { arg.into() };
+
+        // The default list can still be combined with others by its name.
+        if arg == "lofigirl" && !path.exists() {
+            return Ok(Self::lofigirl()?);
+        }
+
+        let raw = fs::read_to_string(&path).await?;
+
+        let name = path
+            .file_stem()
+            .and_then(|x| x.to_str())
+            .ok_or_eyre("invalid track path")?;
+
+        let path = fs::canonicalize(&path).await?;
+        let url = Url::from_file_path(&path).map_err(|()| eyre!("invalid track path"))?;
+        let list = Self::from_text(name, &raw, &url)?;
+
+        if installed {
+            return Ok(list);
+        }
+
+        Ok(Self {
+            id: Self::id(name, &path.to_string_lossy()),
+            ..list
+        })
+    }
+
+    /// Parses the text of a list, where `url` is where it came from, which is a `file://` one for local lists.
+    ///
+    /// Playlists from other players are converted, instead of being parsed normally.
+    fn from_text(name: &str, raw: &str, url: &Url) -> eyre::Result<Self> {
+        let path = url
+            .to_file_path()
+            .unwrap_or_else(|()| PathBuf::from(url.path()));
+
+        if let Some(kind) = playlist::Kind::detect(&path, raw) {
+            let entries = playlist::parse(kind, raw, url)
+                .wrap_err_with(|| format!("invalid playlist {name}"))?;
+
+            return Ok(Self::from_entries(name, Header::default(), entries));
+        }
+
+        Self::new(name, raw).wrap_err_with(|| format!("invalid track list {name}"))
+    }
+
+    /// Reads a [List] from the filesystem using the CLI arguments provided.
+    ///
+    /// If more than one list is given, then they're combined with [`List::combine`].
+    /// `offline` is only used for lists which are URLs, see [`remote::load`].
+    pub async fn load(tracks: &[String], offline: bool) -> eyre::Result<Self> {
+        match tracks {
+            [] => Ok(Self::lofigirl()?),
+            [arg] => Self::load_one(Self::split_ratio(arg).0, offline).await,
+            args => {
+                let mut lists = Vec::with_capacity(args.len());
+                for arg in args {
+                    let (arg, ratio) = Self::split_ratio(arg);
+                    lists.push((Self::load_one(arg, offline).await?, ratio));
+                }
+
+                Ok(Self::combine(lists))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use futures::{stream::FuturesOrdered, StreamExt as _};
+    use reqwest::Client;
+    use tokio::time::sleep;
+
+    use super::{List, Order};
+    use crate::tracks::cache::Cache;
+
+    /// Tracks have to reach the buffer in sequential order even when several are downloaded
+    /// at once & the later ones finish first, which is how the downloader uses [`List::pick`].
+    #[tokio::test]
+    async fn sequential_order_with_parallel_downloads() {
+        let dir = std::env::temp_dir().join(format!("lowfi-sequential-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..4 {
+            fs::write(dir.join(format!("{i}.mp3")), [0; 16]).unwrap();
+        }
+
+        let text = format!("file://{}/\n0.mp3\n1.mp3\n2.mp3\n3.mp3", dir.display());
+        let list = List::new("test", &text).unwrap();
+        list.set_order(Order::Sequential);
+
+        let client = Client::new();
+        let cache = Cache::new(0).await.unwrap();
+
+        let mut downloads = FuturesOrdered::new();
+        for delay in (0..4).rev() {
+            let entry = list.pick(&cache, false, &[]).await.unwrap();
+            let (list, client, cache) = (&list, &client, &cache);
+            downloads.push_back(async move {
+                // The first tracks take the longest, so they'd come last if they were added as they finished.
+                sleep(Duration::from_millis(delay * 20)).await;
+                list.fetch(&entry, client, cache).await.unwrap().full_path
+            });
+        }
+
+        let order: Vec<String> = downloads.collect().await;
+        fs::remove_dir_all(&dir).unwrap();
+
+        let expected: Vec<String> = (0..4)
+            .map(|i| format!("file://{}/{i}.mp3", dir.display()))
+            .collect();
+        assert_eq!(order, expected);
     }
 }
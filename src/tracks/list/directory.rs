@@ -0,0 +1,148 @@
+//! Contains the [`Directory`], which lets a folder of music be played
+//! without having to write a track list for it.
+//!
+//! Every supported track in the directory & its subdirectories becomes a track in the list,
+//! and since they don't have custom names, they're named from their tags or filenames.
+//! The directory is scanned again every so often, so that new tracks can be played.
+
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use eyre::{bail, OptionExt as _};
+use tokio::task;
+
+use crate::tracks::format::Format;
+
+use super::parse::Entry;
+
+/// How long to wait between scans of the directory.
+const RESCAN: Duration = Duration::from_secs(30);
+
+/// A directory which a list was built from.
+pub struct Directory {
+    /// The path of the directory, which has already been canonicalized.
+    path: PathBuf,
+
+    /// The base of every track, which is the directory as a `file://` path.
+    base: Arc<str>,
+
+    /// When the directory was last scanned.
+    scanned: Mutex<Instant>,
+}
+
+impl Directory {
+    /// Finds every playable track in `dir` & its subdirectories, adding their paths to `tracks`.
+    ///
+    /// Symbolic links to directories aren't followed, so that this can't end up in a loop.
+    fn walk(dir: &Path, tracks: &mut Vec<PathBuf>) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if entry.file_type()?.is_dir() {
+                Self::walk(&path, tracks)?;
+            } else if path.is_file()
+                && path
+                    .to_str()
+                    .and_then(Format::from_extension)
+                    .is_some_and(Format::supported)
+            {
+                tracks.push(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Scans the directory for tracks, returning their paths relative to it in alphabetical order.
+    ///
+    /// This reads the entire directory tree, so it shouldn't be called directly in async code.
+    fn scan(&self) -> io::Result<Vec<String>> {
+        let mut tracks = Vec::new();
+        Self::walk(&self.path, &mut tracks)?;
+
+        let mut relative: Vec<String> = tracks
+            .iter()
+            .filter_map(|x| x.strip_prefix(&self.path).ok())
+            .filter_map(|x| {
+                let components: Option<Vec<&str>> =
+                    x.components().map(|x| x.as_os_str().to_str()).collect();
+
+                components.map(|x| x.join("/"))
+            })
+            .collect();
+
+        relative.sort_unstable();
+        Ok(relative)
+    }
+
+    /// Scans the directory in the background, so that it doesn't block the runtime.
+    async fn scan_async(self: &Arc<Self>) -> io::Result<Vec<String>> {
+        let directory = Arc::clone(self);
+        task::spawn_blocking(move || directory.scan())
+            .await
+            .map_err(io::Error::other)?
+    }
+
+    /// Gets the canonical path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Loads a directory, returning it along with a track for every file that was found.
+    pub async fn load(path: &Path) -> eyre::Result<(Arc<Self>, Vec<Entry>)> {
+        let path = tokio::fs::canonicalize(path).await?;
+        let base = format!(
+            "file://{}/",
+            path.to_str().ok_or_eyre("invalid track directory")?
+        );
+
+        let directory = Arc::new(Self {
+            path,
+            base: base.into(),
+            scanned: Mutex::new(Instant::now()),
+        });
+
+        let tracks = directory.scan_async().await?;
+        if tracks.is_empty() {
+            bail!("the directory doesn't have any tracks");
+        }
+
+        let entries = tracks
+            .into_iter()
+            .map(|x| Entry::new(x, &directory.base))
+            .collect();
+
+        Ok((directory, entries))
+    }
+
+    /// Scans the directory again if enough time has passed since the last scan,
+    /// returning any tracks which aren't already in `entries`.
+    pub async fn rescan(self: &Arc<Self>, entries: &[Entry]) -> Vec<Entry> {
+        {
+            let mut scanned = self.scanned.lock().unwrap();
+            if scanned.elapsed() < RESCAN {
+                return Vec::new();
+            }
+
+            *scanned = Instant::now();
+        }
+
+        // If the directory can't be read right now, then it's tried again next time.
+        let Ok(tracks) = self.scan_async().await else {
+            return Vec::new();
+        };
+
+        let known: HashSet<&str> = entries.iter().map(|x| x.path.as_str()).collect();
+        tracks
+            .into_iter()
+            .filter(|x| !known.contains(x.as_str()))
+            .map(|x| Entry::new(x, &self.base))
+            .collect()
+    }
+}
@@ -0,0 +1,440 @@
+//! Parses the text of a track list into its [`Header`] & [`Entry`]s.
+//!
+//! Besides the original format, where the first line is the base and every other line
+//! is a track, this also supports blank lines, `#` comments, directives like `#name:`,
+//! and attributes at the end of a track after a ` | `.
+
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::tracks::{tags::Tags, TrackName};
+
+/// Every attribute which a track can be given.
+const ATTRIBUTES: [&str; 5] = ["artist", "title", "album", "gain", "weight"];
+
+/// Something that's wrong with a single line of a list.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ErrorKind {
+    #[error("`#{0}:` has to come before any tracks")]
+    LateDirective(String),
+
+    #[error("the track doesn't have a path")]
+    MissingPath,
+
+    #[error("`{0}` should be written as `key=value`")]
+    InvalidAttribute(String),
+
+    #[error("`{0}` isn't a known attribute")]
+    UnknownAttribute(String),
+
+    #[error("`{0}` is given more than once")]
+    DuplicateAttribute(String),
+
+    #[error("`{1}` isn't a valid value for `{0}`")]
+    InvalidValue(String, String),
+}
+
+/// The error type for parsing lists.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("line {0}: {1}")]
+    Line(usize, ErrorKind),
+
+    #[error("the list doesn't have any tracks")]
+    Empty,
+}
+
+/// The metadata of a list, which is given with directives at the top of it.
+#[derive(Debug, Default, Clone)]
+pub struct Header {
+    /// The human readable name of the list, from `#name:`.
+    pub name: Option<String>,
+
+    /// The license of the tracks in the list, from `#license:`.
+    pub license: Option<String>,
+}
+
+/// A single track in a list.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// The path of the track, exactly as it was written.
+    pub path: String,
+
+    /// The base which was in use when the track was written.
+    base: Arc<str>,
+
+    /// The custom display name, if there is one.
+    pub name: Option<String>,
+
+    /// The attributes of the track, in the order they were written.
+    pub attributes: Vec<(String, String)>,
+
+    /// The title of the list which the track came from, which
+    /// is only set if several lists have been combined.
+    pub list: Option<String>,
+
+    /// The ID of the list which the track came from, which is what its quarantine & shuffle
+    /// bag are saved under. Like `list`, this is only set if several lists have been combined.
+    pub source: Option<String>,
+
+    /// The line of the list that the track is on, if it came from one.
+    pub line: Option<usize>,
+}
+
+impl Entry {
+    /// Creates a track with just a path, which is relative to `base`.
+    pub fn new(path: String, base: &Arc<str>) -> Self {
+        Self {
+            path,
+            base: Arc::clone(base),
+            name: None,
+            attributes: Vec::new(),
+            list: None,
+            source: None,
+            line: None,
+        }
+    }
+
+    /// Gets the full path of the track, which is the base with the track appended.
+    pub fn full_path(&self) -> String {
+        // If the track has a protocol, then we should ignore the base for it.
+        if self.path.contains("://") {
+            self.path.clone()
+        } else {
+            format!("{}{}", self.base, self.path)
+        }
+    }
+
+    /// Gets the name of the track, which is only formatted if it has a custom one.
+    pub fn track_name(&self) -> TrackName {
+        self.name
+            .clone()
+            .map_or_else(|| TrackName::Raw(self.path.clone()), TrackName::Formatted)
+    }
+
+    /// Gets the value of an attribute, if the track has it.
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(x, _)| x == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses a weight, which has to be a positive number.
+    fn parse_weight(value: &str) -> Option<f64> {
+        value
+            .parse()
+            .ok()
+            .filter(|x: &f64| x.is_finite() && *x > 0.0)
+    }
+
+    /// Gets how likely the track is to be picked compared to the others,
+    /// which is one unless it was given a `weight`.
+    pub fn weight(&self) -> f64 {
+        self.attribute("weight")
+            .and_then(Self::parse_weight)
+            .unwrap_or(1.0)
+    }
+
+    /// Gets the tags which were given as attributes, which take priority over the ones in the track itself.
+    pub fn tags(&self) -> Tags {
+        Tags {
+            artist: self.attribute("artist").map(str::to_owned),
+            title: self.attribute("title").map(str::to_owned),
+            album: self.attribute("album").map(str::to_owned),
+            gain: self.attribute("gain").and_then(Tags::parse_gain),
+            peak: None,
+        }
+    }
+
+    /// Writes the track back out as a line, but with `path` instead of its original path.
+    pub fn to_line(&self, path: &str) -> String {
+        let mut line = path.to_owned();
+        if let Some(name) = &self.name {
+            line.push('!');
+            line.push_str(name);
+        }
+
+        if !self.attributes.is_empty() {
+            let attributes: Vec<String> = self
+                .attributes
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect();
+
+            line.push_str(" | ");
+            line.push_str(&attributes.join("; "));
+        }
+
+        line
+    }
+
+    /// Parses the attributes at the end of a track, which look like `key=value; key=value`.
+    fn attributes(text: &str) -> Result<Vec<(String, String)>, ErrorKind> {
+        let mut attributes: Vec<(String, String)> = Vec::new();
+
+        for attribute in text.split(';').map(str::trim).filter(|x| !x.is_empty()) {
+            let Some((key, value)) = attribute.split_once('=') else {
+                return Err(ErrorKind::InvalidAttribute(attribute.to_owned()));
+            };
+            let (key, value) = (key.trim(), value.trim());
+
+            if !ATTRIBUTES.contains(&key) {
+                return Err(ErrorKind::UnknownAttribute(key.to_owned()));
+            }
+
+            if attributes.iter().any(|(x, _)| x == key) {
+                return Err(ErrorKind::DuplicateAttribute(key.to_owned()));
+            }
+
+            let valid = match key {
+                "gain" => Tags::parse_gain(value).is_some(),
+                "weight" => Self::parse_weight(value).is_some(),
+                _ => true,
+            };
+
+            if !valid {
+                return Err(ErrorKind::InvalidValue(key.to_owned(), value.to_owned()));
+            }
+
+            attributes.push((key.to_owned(), value.to_owned()));
+        }
+
+        Ok(attributes)
+    }
+
+    /// Parses a single track, which looks like `path!name | key=value`,
+    /// where both the name & attributes are optional.
+    ///
+    /// Names from before attributes existed can have a ` | ` in them, so the end is only
+    /// treated as attributes if it's made up of known `key=value` pairs. Otherwise, it's
+    /// just part of the name, like it always was.
+    fn parse(text: &str, base: &Arc<str>) -> Result<Self, ErrorKind> {
+        let split = text
+            .rsplit_once(" | ")
+            .map(|(track, attributes)| (track, Self::attributes(attributes)));
+
+        let (track, attributes) = match split {
+            Some((track, Ok(attributes))) => (track.trim_end(), attributes),
+            Some((_, Err(ErrorKind::InvalidAttribute(_) | ErrorKind::UnknownAttribute(_))))
+            | None => (text, Vec::new()),
+            Some((_, Err(error))) => return Err(error),
+        };
+
+        let (path, name) = track
+            .split_once('!')
+            .map_or((track, None), |(path, name)| (path, Some(name)));
+
+        if path.is_empty() {
+            return Err(ErrorKind::MissingPath);
+        }
+
+        Ok(Self {
+            path: path.to_owned(),
+            base: Arc::clone(base),
+            name: name.filter(|x| !x.is_empty()).map(str::to_owned),
+            attributes,
+            list: None,
+            source: None,
+            line: None,
+        })
+    }
+}
+
+/// Parses the entire text of a list.
+///
+/// The first line which isn't blank, a comment, or a directive is the base, unless
+/// `#base:` was used before it. `#base:` can also be used further down to change the
+/// base for every track after it, while `#name:` & `#license:` have to be at the top.
+pub fn parse(text: &str) -> Result<(Header, Vec<Entry>), ParseError> {
+    let mut header = Header::default();
+    let mut base: Option<Arc<str>> = None;
+    let mut entries = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        // Anything starting with a `#` which isn't a directive is just a comment.
+        if let Some((key, value)) = line.strip_prefix('#').and_then(|x| x.split_once(':')) {
+            let value = value.trim().to_owned();
+            let field = match key {
+                "base" => {
+                    base = Some(value.into());
+                    continue;
+                }
+                "name" => &mut header.name,
+                "license" => &mut header.license,
+                _ => continue,
+            };
+
+            if !entries.is_empty() {
+                let error = ErrorKind::LateDirective(key.to_owned());
+                return Err(ParseError::Line(number, error));
+            }
+
+            *field = Some(value);
+            continue;
+        }
+
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let Some(base) = &base else {
+            base = Some(line.into());
+            continue;
+        };
+
+        let mut entry = Entry::parse(line, base).map_err(|x| ParseError::Line(number, x))?;
+        entry.line = Some(number);
+        entries.push(entry);
+    }
+
+    if entries.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    Ok((header, entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::{parse, Entry, ErrorKind, ParseError};
+
+    /// Parses a single track with an empty base.
+    fn entry(text: &str) -> Result<Entry, ErrorKind> {
+        Entry::parse(text, &Arc::from(""))
+    }
+
+    #[test]
+    fn original_format() {
+        let (header, entries) = parse("https://example.com/\none.mp3\ntwo.mp3!Two").unwrap();
+
+        assert_eq!(header.name, None);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].full_path(), "https://example.com/one.mp3");
+        assert_eq!(entries[1].name.as_deref(), Some("Two"));
+    }
+
+    #[test]
+    fn errors_have_line_numbers() {
+        let text =
+            "#name: Test\n\nhttps://example.com/\n# a comment\none.mp3\ntwo.mp3 | gain=1; gain=2";
+
+        assert_eq!(
+            parse(text).unwrap_err(),
+            ParseError::Line(6, ErrorKind::DuplicateAttribute("gain".to_owned()))
+        );
+    }
+
+    #[test]
+    fn entries_have_line_numbers() {
+        let (_, entries) = parse("\n# comment\nhttps://example.com/\n\none.mp3").unwrap();
+
+        assert_eq!(entries[0].line, Some(5));
+    }
+
+    #[test]
+    fn late_directives() {
+        let text = "https://example.com/\none.mp3\n#name: Too Late";
+
+        assert_eq!(
+            parse(text).unwrap_err(),
+            ParseError::Line(3, ErrorKind::LateDirective("name".to_owned()))
+        );
+    }
+
+    #[test]
+    fn late_base() {
+        let text = "#name: Test\n#base: https://a.com/\none.mp3\n#base: https://b.com/\ntwo.mp3";
+        let (header, entries) = parse(text).unwrap();
+
+        assert_eq!(header.name.as_deref(), Some("Test"));
+        assert_eq!(entries[0].full_path(), "https://a.com/one.mp3");
+        assert_eq!(entries[1].full_path(), "https://b.com/two.mp3");
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(
+            parse("# just a comment\nhttps://example.com/").unwrap_err(),
+            ParseError::Empty
+        );
+    }
+
+    #[test]
+    fn attributes() {
+        let entry = entry("track.mp3!Name | artist=Someone; weight=2").unwrap();
+
+        assert_eq!(entry.name.as_deref(), Some("Name"));
+        assert_eq!(entry.attribute("artist"), Some("Someone"));
+        assert!((entry.weight() - 2.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn duplicate_attributes() {
+        assert_eq!(
+            entry("track.mp3 | album=A; album=B").unwrap_err(),
+            ErrorKind::DuplicateAttribute("album".to_owned())
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert_eq!(
+            entry("track.mp3 | weight=-1").unwrap_err(),
+            ErrorKind::InvalidValue("weight".to_owned(), "-1".to_owned())
+        );
+    }
+
+    #[test]
+    fn unknown_attributes_are_part_of_the_name() {
+        let entry = entry("track.mp3!Chill | mix=2").unwrap();
+
+        assert_eq!(entry.name.as_deref(), Some("Chill | mix=2"));
+        assert!(entry.attributes.is_empty());
+    }
+
+    #[test]
+    fn old_names_with_a_separator() {
+        let entry = entry("track.mp3!Morning | Evening").unwrap();
+
+        assert_eq!(entry.path, "track.mp3");
+        assert_eq!(entry.name.as_deref(), Some("Morning | Evening"));
+        assert!(entry.attributes.is_empty());
+    }
+
+    #[test]
+    fn missing_path() {
+        assert_eq!(entry("!Name").unwrap_err(), ErrorKind::MissingPath);
+    }
+
+    #[test]
+    fn round_trip() {
+        for line in [
+            "track.mp3",
+            "track.mp3!Name",
+            "track.mp3 | gain=-3.5",
+            "track.mp3!Name | artist=Someone; weight=2",
+            "track.mp3!Morning | Evening",
+            "track.mp3!Morning | Evening | title=Noon",
+        ] {
+            let original = entry(line).unwrap();
+            let written = original.to_line(&original.path);
+            let parsed = entry(&written).unwrap();
+
+            assert_eq!(written, line);
+            assert_eq!(parsed.path, original.path);
+            assert_eq!(parsed.name, original.name);
+            assert_eq!(parsed.attributes, original.attributes);
+        }
+    }
+}
@@ -0,0 +1,330 @@
+//! Converts playlists from other players into track lists, and back again.
+//!
+//! M3U (including M3U8 & `#EXTINF` titles), PLS and XSPF playlists are supported.
+//! Relative paths in a playlist are resolved against the directory that it's in,
+//! or the URL that it was fetched from, and any titles are used as the custom names of the tracks.
+
+use std::{collections::BTreeMap, path::Path, sync::Arc};
+
+use url::Url;
+
+use super::parse::{Entry, ParseError};
+
+/// A kind of playlist which can be imported.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Kind {
+    /// M3U & M3U8, which are just paths with optional `#EXTINF` lines before them.
+    M3u,
+
+    /// PLS, which is an INI file with numbered `FileN` & `TitleN` keys.
+    Pls,
+
+    /// XSPF, which is XML with a `<track>` for each track.
+    Xspf,
+}
+
+impl Kind {
+    /// Detects the kind of playlist from its extension, or failing
+    /// that its contents, returning [None] if it's a normal track list.
+    pub fn detect(path: &Path, text: &str) -> Option<Self> {
+        let extension = path
+            .extension()
+            .and_then(|x| x.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("m3u" | "m3u8") => return Some(Self::M3u),
+            Some("pls") => return Some(Self::Pls),
+            Some("xspf") => return Some(Self::Xspf),
+            _ => {}
+        }
+
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if text.starts_with("#EXTM3U") {
+            Some(Self::M3u)
+        } else if text.to_ascii_lowercase().starts_with("[playlist]") {
+            Some(Self::Pls)
+        } else if text.starts_with("<?xml") && text.contains("<playlist") {
+            Some(Self::Xspf)
+        } else {
+            None
+        }
+    }
+}
+
+/// Turns a location from a playlist into a full path which lowfi can use,
+/// where `base` is the URL of the playlist itself, which is a `file://` one for local playlists.
+fn resolve(location: &str, base: &Url) -> Option<String> {
+    let location = location.trim();
+    if location.is_empty() {
+        return None;
+    }
+
+    if location.contains("://") {
+        // File URLs are percent encoded, but lowfi reads `file://` paths as they are.
+        return Some(match Url::parse(location).ok()?.to_file_path() {
+            Ok(path) => format!("file://{}", path.to_str()?),
+            Err(()) => location.to_owned(),
+        });
+    }
+
+    // Playlists made on Windows use backslashes, which won't work anywhere else.
+    #[cfg(not(windows))]
+    let location = location.replace('\\', "/");
+
+    // Local paths aren't percent encoded, so they're joined as paths rather than as URLs.
+    let Ok(playlist) = base.to_file_path() else {
+        return Some(base.join(&location).ok()?.into());
+    };
+
+    let path = playlist.parent()?.join(location);
+    Some(format!("file://{}", path.to_str()?))
+}
+
+/// Creates a track from a location & title, which are both straight from the playlist.
+fn entry(location: &str, title: Option<&str>, base: &Url) -> Option<Entry> {
+    let mut entry = Entry::new(resolve(location, base)?, &Arc::from(""));
+    entry.name = title
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .map(str::to_owned);
+
+    Some(entry)
+}
+
+/// Parses an M3U playlist, where titles come from the `#EXTINF` line before each track.
+fn m3u(text: &str, base: &Url) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut title = None;
+
+    for line in text
+        .lines()
+        .map(|x| x.trim_start_matches('\u{feff}').trim())
+    {
+        if let Some(info) = line.strip_prefix("#EXTINF:") {
+            // This looks like `#EXTINF:123,Artist - Title`, where the number is the duration.
+            title = info.split_once(',').map(|(_, title)| title);
+        } else if !line.is_empty() && !line.starts_with('#') {
+            entries.extend(entry(line, title.take(), base));
+        }
+    }
+
+    entries
+}
+
+/// Parses a PLS playlist, where `FileN` is the location of a track and `TitleN` is its title.
+fn pls(text: &str, base: &Url) -> Vec<Entry> {
+    let mut tracks: BTreeMap<u32, (Option<&str>, Option<&str>)> = BTreeMap::new();
+
+    for (key, value) in text.lines().filter_map(|x| x.split_once('=')) {
+        let key = key.trim().to_ascii_lowercase();
+        let (field, number) = if let Some(number) = key.strip_prefix("file") {
+            (0, number)
+        } else if let Some(number) = key.strip_prefix("title") {
+            (1, number)
+        } else {
+            continue;
+        };
+
+        let Ok(number) = number.parse() else {
+            continue;
+        };
+
+        let track = tracks.entry(number).or_default();
+        if field == 0 {
+            track.0 = Some(value);
+        } else {
+            track.1 = Some(value);
+        }
+    }
+
+    tracks
+        .into_values()
+        .filter_map(|(location, title)| entry(location?, title, base))
+        .collect()
+}
+
+/// Replaces the predefined XML entities in some text.
+fn unescape(text: &str) -> String {
+    text.trim()
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Gets the text inside of the first `<tag>` in some XML.
+///
+/// This isn't a real XML parser, but XSPF files are simple enough that it doesn't need to be.
+fn element(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = xml[start..].find(&format!("</{tag}>"))? + start;
+
+    Some(unescape(&xml[start..end]))
+}
+
+/// Parses an XSPF playlist, where the title is made from the `<creator>` & `<title>` of each track.
+fn xspf(text: &str, base: &Url) -> Vec<Entry> {
+    text.split("<track>")
+        .skip(1)
+        .filter_map(|track| {
+            let track = track.split_once("</track>")?.0;
+            let location = base.join(&element(track, "location")?).ok()?;
+
+            let title = match (element(track, "creator"), element(track, "title")) {
+                (Some(creator), Some(title)) => Some(format!("{creator} - {title}")),
+                (None, title) => title,
+                (creator, None) => creator,
+            };
+
+            entry(location.as_str(), title.as_deref(), base)
+        })
+        .collect()
+}
+
+/// Parses a playlist into tracks, where `url` is where it is, which is a `file://` one for local playlists.
+pub fn parse(kind: Kind, text: &str, url: &Url) -> Result<Vec<Entry>, ParseError> {
+    let entries = match kind {
+        Kind::M3u => m3u(text, url),
+        Kind::Pls => pls(text, url),
+        Kind::Xspf => xspf(text, url),
+    };
+
+    if entries.is_empty() {
+        return Err(ParseError::Empty);
+    }
+
+    Ok(entries)
+}
+
+/// Writes tracks out as an M3U playlist, so that they can be played in other players.
+///
+/// Tracks which have a custom name, or were given an artist or title, get an `#EXTINF` line.
+pub fn to_m3u(title: &str, entries: &[Entry]) -> String {
+    let mut lines = vec![String::from("#EXTM3U"), format!("#PLAYLIST:{title}")];
+
+    for entry in entries {
+        let tags = entry.tags();
+        let name = entry
+            .name
+            .clone()
+            .or_else(|| match (tags.artist, tags.title) {
+                (Some(artist), Some(title)) => Some(format!("{artist} - {title}")),
+                (None, title) => title,
+                (artist, None) => artist,
+            });
+
+        if let Some(name) = name {
+            lines.push(format!("#EXTINF:-1,{name}"));
+        }
+
+        // Other players don't always understand `file://`, but they all understand plain paths.
+        let full_path = entry.full_path();
+        let path = full_path.strip_prefix("file://").unwrap_or(&full_path);
+        lines.push(path.to_owned());
+    }
+
+    lines.join("\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use url::Url;
+
+    use super::{parse, to_m3u, Kind};
+
+    /// Where the local playlists in these tests are.
+    fn local() -> Url {
+        Url::from_file_path("/music/lists/evening.m3u").unwrap()
+    }
+
+    #[test]
+    fn detect() {
+        assert_eq!(Kind::detect(Path::new("a.M3U8"), ""), Some(Kind::M3u));
+        assert_eq!(Kind::detect(Path::new("a.pls"), ""), Some(Kind::Pls));
+        assert_eq!(
+            Kind::detect(Path::new("a.txt"), "#EXTM3U\n"),
+            Some(Kind::M3u)
+        );
+        assert_eq!(
+            Kind::detect(Path::new("a"), "[Playlist]\n"),
+            Some(Kind::Pls)
+        );
+        assert_eq!(
+            Kind::detect(Path::new("a"), "<?xml version=\"1.0\"?>\n<playlist>"),
+            Some(Kind::Xspf)
+        );
+        assert_eq!(
+            Kind::detect(Path::new("a.txt"), "https://example.com/\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn m3u() {
+        let text = "#EXTM3U\n#EXTINF:123,Someone - Something\n../one.mp3\n\n/abs/two.mp3\n";
+        let entries = parse(Kind::M3u, text, &local()).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].full_path(), "file:///music/lists/../one.mp3");
+        assert_eq!(entries[0].name.as_deref(), Some("Someone - Something"));
+        assert_eq!(entries[1].full_path(), "file:///abs/two.mp3");
+        assert_eq!(entries[1].name, None);
+    }
+
+    #[test]
+    fn pls() {
+        let text = "[playlist]\nFile2=two.mp3\nTitle1=One\nFile1=one.mp3\nNumberOfEntries=2\n";
+        let entries = parse(Kind::Pls, text, &local()).unwrap();
+
+        assert_eq!(entries[0].full_path(), "file:///music/lists/one.mp3");
+        assert_eq!(entries[0].name.as_deref(), Some("One"));
+        assert_eq!(entries[1].full_path(), "file:///music/lists/two.mp3");
+    }
+
+    #[test]
+    fn xspf() {
+        let text = "<?xml version=\"1.0\"?><playlist><trackList>\
+            <track><location>a%20b.mp3</location><creator>Tom &amp; Jerry</creator><title>Chase</title></track>\
+            <track><location>file:///abs/c.mp3</location></track>\
+            </trackList></playlist>";
+        let entries = parse(Kind::Xspf, text, &local()).unwrap();
+
+        assert_eq!(entries[0].full_path(), "file:///music/lists/a b.mp3");
+        assert_eq!(entries[0].name.as_deref(), Some("Tom & Jerry - Chase"));
+        assert_eq!(entries[1].full_path(), "file:///abs/c.mp3");
+    }
+
+    #[test]
+    fn remote() {
+        let url = Url::parse("https://example.com/lists/evening.m3u").unwrap();
+        let entries = parse(
+            Kind::M3u,
+            "one.mp3\n../two.mp3\nhttps://other.com/three.mp3",
+            &url,
+        )
+        .unwrap();
+
+        assert_eq!(entries[0].full_path(), "https://example.com/lists/one.mp3");
+        assert_eq!(entries[1].full_path(), "https://example.com/two.mp3");
+        assert_eq!(entries[2].full_path(), "https://other.com/three.mp3");
+    }
+
+    #[test]
+    fn empty() {
+        assert!(parse(Kind::M3u, "#EXTM3U\n", &local()).is_err());
+    }
+
+    #[test]
+    fn export() {
+        let entries = parse(Kind::M3u, "#EXTINF:-1,One\none.mp3\ntwo.mp3\n", &local()).unwrap();
+
+        assert_eq!(
+            to_m3u("Evening", &entries),
+            "#EXTM3U\n#PLAYLIST:Evening\n#EXTINF:-1,One\n/music/lists/one.mp3\n/music/lists/two.mp3\n"
+        );
+    }
+}
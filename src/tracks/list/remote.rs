@@ -0,0 +1,169 @@
+//! Fetches track lists from a URL, so that they don't have to be downloaded by hand.
+//!
+//! A copy of every fetched list is kept in `lists` under [`data_dir`], named after its [`List::id`],
+//! along with the `ETag` & `Last-Modified` headers it was sent with. These are used to check
+//! whether the list has changed on later runs, and the copy is used as-is if the
+//! server can't be reached.
+
+use std::time::Duration;
+
+use eyre::{bail, OptionExt as _};
+use reqwest::{header, Client, StatusCode};
+use tokio::fs;
+use url::Url;
+
+use crate::data_dir;
+
+use super::List;
+
+/// How long to wait for the server before falling back to the stored copy.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether a list argument is a URL rather than a name or path.
+pub fn is_remote(arg: &str) -> bool {
+    arg.starts_with("http://") || arg.starts_with("https://")
+}
+
+/// The validators which a list was last sent with, which are used
+/// to ask the server to only send the list again if it's changed.
+#[derive(Default)]
+struct Validators {
+    /// The URL that the list was fetched from, since two
+    /// different URLs can end up with the same name.
+    url: String,
+
+    /// The `ETag` header.
+    etag: Option<String>,
+
+    /// The `Last-Modified` header.
+    last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Parses the validators, which are saved as `key value` on each line.
+    fn parse(text: &str) -> Self {
+        let mut validators = Self::default();
+        for (key, value) in text.lines().filter_map(|x| x.split_once(' ')) {
+            match key {
+                "url" => value.clone_into(&mut validators.url),
+                "etag" => validators.etag = Some(value.to_owned()),
+                "last-modified" => validators.last_modified = Some(value.to_owned()),
+                _ => {}
+            }
+        }
+
+        validators
+    }
+
+    /// Writes the validators back out in the same format that [`Validators::parse`] reads.
+    fn to_text(&self) -> String {
+        let mut lines = vec![format!("url {}", self.url)];
+        lines.extend(self.etag.as_ref().map(|x| format!("etag {x}")));
+        lines.extend(
+            self.last_modified
+                .as_ref()
+                .map(|x| format!("last-modified {x}")),
+        );
+
+        lines.join("\n")
+    }
+}
+
+/// Gets the name of a list from its URL, which is the
+/// file name without its extension, like `chillhop`.
+fn name(url: &Url) -> Option<String> {
+    let file = url.path_segments()?.rev().find(|x| !x.is_empty())?;
+    let name = file.rsplit_once('.').map_or(file, |(name, _)| name);
+
+    (!name.is_empty()).then(|| name.to_owned())
+}
+
+/// Gets a header as a string, if the server sent it.
+fn header(headers: &header::HeaderMap, key: header::HeaderName) -> Option<String> {
+    headers
+        .get(key)
+        .and_then(|x| x.to_str().ok())
+        .map(str::to_owned)
+}
+
+/// Loads a list from a URL, returning its name & text.
+///
+/// If the list has been fetched before, then the stored copy is used when either the server
+/// says that it hasn't changed, the server can't be reached, the new list is broken, or `offline` is true.
+pub async fn load(arg: &str, offline: bool) -> eyre::Result<(String, String)> {
+    let url = Url::parse(arg)?;
+    let name = name(&url).ok_or_eyre("unable to get a name from the track list url")?;
+
+    let id = List::id(&name, arg);
+    let dir = data_dir()?.join("lists");
+    let path = dir.join(format!("{id}.txt"));
+    let validators_path = dir.join(format!("{id}.headers"));
+
+    let stored = fs::read_to_string(&path).await.ok();
+    let validators = fs::read_to_string(&validators_path)
+        .await
+        .map(|x| Validators::parse(&x))
+        .ok()
+        .filter(|x| x.url == arg);
+
+    // Validators for a different URL would be wrong, and so would the list itself.
+    let stored = stored.filter(|_| validators.is_some());
+
+    if offline {
+        return stored.map(|x| (name, x)).ok_or_eyre(
+            "the track list hasn't been fetched before, so it isn't available offline",
+        );
+    }
+
+    let client = Client::builder()
+        .user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ))
+        .timeout(TIMEOUT)
+        .build()?;
+
+    let mut request = client.get(url.clone());
+    if let (Some(validators), Some(_)) = (&validators, &stored) {
+        if let Some(etag) = &validators.etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+
+    let response = match (request.send().await, &stored) {
+        (Ok(response), _) if response.status() == StatusCode::NOT_MODIFIED => {
+            return stored
+                .map(|x| (name, x))
+                .ok_or_eyre("server said the track list hasn't changed, but it isn't stored");
+        }
+        (Ok(response), _) if response.status().is_success() => response,
+
+        // The stored copy is better than nothing if the server isn't cooperating.
+        (_, Some(stored)) => return Ok((name, stored.clone())),
+        (Ok(response), None) => bail!("server returned {}", response.status()),
+        (Err(error), None) => return Err(error.into()),
+    };
+
+    let validators = Validators {
+        url: arg.to_owned(),
+        etag: header(response.headers(), header::ETAG),
+        last_modified: header(response.headers(), header::LAST_MODIFIED),
+    };
+    let text = response.text().await?;
+
+    // A broken list shouldn't replace one which worked, and the one which worked is still used.
+    if let Err(error) = List::from_text(&name, &text, &url) {
+        return stored.map(|x| (name, x)).ok_or(error);
+    }
+
+    // Failing to store the list just means that it'll be fetched again next time.
+    if fs::create_dir_all(&dir).await.is_ok() && fs::write(&path, &text).await.is_ok() {
+        let _ = fs::write(&validators_path, validators.to_text()).await;
+    }
+
+    Ok((name, text))
+}
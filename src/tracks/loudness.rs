@@ -0,0 +1,207 @@
+//! Measures the loudness of tracks, so that they can all be played at around the same volume.
+//!
+//! This follows EBU R128 (ITU-R BS.1770), which means that the audio is first passed
+//! through a "K-weighting" filter, and then split into overlapping 400ms blocks which
+//! are gated to ignore silence & quiet sections before being averaged.
+
+use std::{
+    f64::consts::PI,
+    io::Cursor,
+    sync::{Arc, OnceLock},
+};
+
+use bytes::Bytes;
+use rodio::Source as _;
+
+use super::{DecodedData, TrackData};
+
+/// The loudness that tracks are normalized to in LUFS, which is the same one that replay gain uses.
+pub const TARGET: f32 = -18.0;
+
+/// The most that a track's volume will be changed by in decibels,
+/// so that very quiet tracks don't have their noise blown up.
+const MAX_GAIN: f32 = 12.0;
+
+/// Blocks quieter than this in LUFS are ignored entirely.
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// Blocks which are this much quieter than the average are ignored.
+const RELATIVE_GATE: f64 = -10.0;
+
+/// The result of measuring a track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loudness {
+    /// The integrated loudness in LUFS.
+    pub integrated: f32,
+
+    /// The loudest sample, where one is full scale.
+    pub peak: f32,
+}
+
+/// How much a track's volume should be changed by, which might not be known until
+/// it has been measured, possibly after the track has already started playing.
+#[derive(Debug, Clone)]
+pub enum Gain {
+    /// The volume multiplier is already known.
+    Known(f32),
+
+    /// The volume multiplier depends on a measurement which may still be running.
+    Measured(Arc<OnceLock<Loudness>>),
+}
+
+impl Gain {
+    /// Gets the volume multiplier, if it's known yet.
+    pub fn get(&self) -> Option<f32> {
+        match self {
+            Self::Known(multiplier) => Some(*multiplier),
+            Self::Measured(loudness) => loudness
+                .get()
+                .map(|x| multiplier(TARGET - x.integrated, Some(x.peak))),
+        }
+    }
+}
+
+/// A second order IIR filter.
+#[derive(Clone, Copy)]
+struct Biquad {
+    /// The numerator coefficients.
+    b: [f64; 3],
+
+    /// The denominator coefficients, not including the first one which is always one.
+    a: [f64; 2],
+
+    /// The two previous states.
+    z: [f64; 2],
+}
+
+impl Biquad {
+    /// Filters a single sample.
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0].mul_add(x, self.z[0]);
+        self.z[0] = self.a[0].mul_add(-y, self.b[1].mul_add(x, self.z[1]));
+        self.z[1] = self.b[2].mul_add(x, -self.a[1] * y);
+
+        y
+    }
+
+    /// The high shelf filter, which is the first stage of K-weighting.
+    fn shelf(sample_rate: f64) -> Self {
+        let k = (PI * 1_681.974_450_955_533 / sample_rate).tan();
+        let q = 0.707_175_236_955_419_6;
+        let vh = 10f64.powf(3.999_843_853_973_347 / 20.0);
+        let vb = vh.powf(0.499_666_774_154_541_6);
+        let a0 = 1.0 + k / q + k * k;
+
+        Self {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+
+    /// The high pass filter, which is the second stage of K-weighting.
+    fn high_pass(sample_rate: f64) -> Self {
+        let k = (PI * 38.135_470_876_024_44 / sample_rate).tan();
+        let q = 0.500_327_037_323_877_3;
+        let a0 = 1.0 + k / q + k * k;
+
+        Self {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2],
+        }
+    }
+}
+
+/// Converts a mean square into loudness in LUFS.
+fn lufs(power: f64) -> f64 {
+    10.0f64.mul_add(power.log10(), -0.691)
+}
+
+/// Decodes an entire track & measures its integrated loudness in LUFS, along with its peak.
+///
+/// This takes a while, so it shouldn't be called directly in async code.
+/// Returns [None] if the track can't be decoded or is completely silent.
+pub fn measure(data: Bytes, full_path: &str) -> Option<Loudness> {
+    let mut decoded = DecodedData::new(TrackData::Full(Cursor::new(data)), full_path).ok()?;
+    let channels = usize::from(decoded.channels());
+    let sample_rate = f64::from(decoded.sample_rate());
+    if channels == 0 {
+        return None;
+    }
+
+    let mut filters = vec![(Biquad::shelf(sample_rate), Biquad::high_pass(sample_rate)); channels];
+
+    // The power of each 100ms segment, four of which make up a block.
+    let frames = decoded.sample_rate() / 10;
+    let segment = usize::try_from(frames).ok()? * channels;
+    let mut segments = Vec::new();
+    let mut sum = 0.0;
+    let mut count = 0;
+    let mut peak: f64 = 0.0;
+
+    for (i, sample) in decoded.by_ref().enumerate() {
+        let sample = f64::from(sample) / 32768.0;
+        peak = peak.max(sample.abs());
+
+        let (shelf, high_pass) = &mut filters[i % channels];
+        let filtered = high_pass.process(shelf.process(sample));
+
+        sum += filtered * filtered;
+        count += 1;
+
+        if count == segment {
+            segments.push(sum / f64::from(frames));
+            sum = 0.0;
+            count = 0;
+        }
+    }
+
+    let blocks: Vec<f64> = segments
+        .windows(4)
+        .map(|x| x.iter().sum::<f64>() / 4.0)
+        .filter(|x| lufs(*x) > ABSOLUTE_GATE)
+        .collect();
+
+    #[expect(
+        clippy::cast_precision_loss,
+        reason = "there will never be anywhere near 2^52 blocks"
+    )]
+    let average = |blocks: &[f64]| blocks.iter().sum::<f64>() / blocks.len() as f64;
+    let threshold = lufs(average(&blocks)) + RELATIVE_GATE;
+    let gated: Vec<f64> = blocks
+        .into_iter()
+        .filter(|x| lufs(*x) > threshold)
+        .collect();
+
+    if gated.is_empty() {
+        return None;
+    }
+
+    #[expect(
+        clippy::cast_possible_truncation,
+        reason = "loudness is nowhere near the limits of an f32"
+    )]
+    Some(Loudness {
+        integrated: lufs(average(&gated)) as f32,
+        peak: peak as f32,
+    })
+}
+
+/// Gets the volume multiplier which should be applied to a track, given
+/// its gain in decibels, which is clamped to [`MAX_GAIN`].
+///
+/// If the track's `peak` is known, then it's never made louder than
+/// that peak allows, since it would clip otherwise.
+pub fn multiplier(gain: f32, peak: Option<f32>) -> f32 {
+    let multiplier = 10f32.powf(gain.clamp(-MAX_GAIN, MAX_GAIN) / 20.0);
+
+    match peak {
+        Some(peak) if multiplier > 1.0 && peak > 0.0 => multiplier.min(peak.recip().max(1.0)),
+        _ => multiplier,
+    }
+}
@@ -0,0 +1,176 @@
+//! A decoder for Ogg Opus tracks, which [rodio] can't decode by itself.
+//!
+//! This is only compiled with the `opus` feature, since it depends on libopus.
+
+use std::{
+    io::{Seek, SeekFrom},
+    time::Duration,
+};
+
+use audiopus::{coder::Decoder, Channels, MutSignals, SampleRate};
+use ogg::PacketReader;
+use rodio::{source::SeekError, Source};
+
+use super::{TrackData, TrackError};
+
+/// Opus is always decoded at 48kHz, no matter what the original sample rate was.
+const SAMPLE_RATE: u32 = 48000;
+
+/// The most samples per channel that a single Opus packet can contain, which is 120ms.
+const MAX_PACKET_SAMPLES: usize = 5760;
+
+/// Decodes an Ogg Opus stream into interleaved samples.
+pub struct OpusDecoder {
+    /// The Ogg demuxer.
+    reader: PacketReader<TrackData>,
+
+    /// The actual Opus decoder.
+    decoder: Decoder,
+
+    /// The serial of the Opus stream inside of the Ogg container.
+    serial: u32,
+
+    /// The amount of channels, which is either one or two.
+    channels: u16,
+
+    /// The most recently decoded packet.
+    buffer: Vec<i16>,
+
+    /// How far into `buffer` playback currently is.
+    position: usize,
+
+    /// How many samples still need to be skipped from the start of the stream.
+    skip: usize,
+
+    /// The total duration, if it could be found.
+    duration: Option<Duration>,
+}
+
+impl OpusDecoder {
+    /// Finds the granule position of the last page of a complete Ogg stream,
+    /// which is the total number of samples per channel.
+    ///
+    /// This is skipped for tracks which are still being streamed, since it requires the end of the data.
+    fn last_granule(data: &TrackData) -> Option<u64> {
+        let TrackData::Full(cursor) = data else {
+            return None;
+        };
+
+        let data = cursor.get_ref();
+        let tail = &data[data.len().saturating_sub(65536)..];
+        let page = tail.windows(4).rposition(|x| x == b"OggS")?;
+        let granule = tail.get(page + 6..page + 14)?;
+
+        Some(u64::from_le_bytes(granule.try_into().ok()?))
+    }
+
+    /// Reads the Opus headers and creates the decoder.
+    pub fn new(mut data: TrackData) -> Result<Self, TrackError> {
+        data.seek(SeekFrom::Start(0))?;
+        let granule = Self::last_granule(&data);
+        let mut reader = PacketReader::new(data);
+
+        // The identification header, which is the first packet of the stream.
+        let head = reader.read_packet_expected()?;
+        if !head.data.starts_with(b"OpusHead") || head.data.len() < 19 {
+            return Err(TrackError::InvalidOpus);
+        }
+
+        let channels = match head.data[9] {
+            1 => Channels::Mono,
+            2 => Channels::Stereo,
+            _ => return Err(TrackError::InvalidOpus),
+        };
+        let pre_skip = u16::from_le_bytes([head.data[10], head.data[11]]);
+
+        // The comment header, which isn't needed here.
+        reader.read_packet_expected()?;
+
+        Ok(Self {
+            reader,
+            decoder: Decoder::new(SampleRate::Hz48000, channels)?,
+            serial: head.stream_serial(),
+            channels: head.data[9].into(),
+            buffer: Vec::new(),
+            position: 0,
+            skip: usize::from(pre_skip) * usize::from(head.data[9]),
+
+            // There are 48 samples in each millisecond at 48kHz.
+            duration: granule
+                .map(|x| Duration::from_millis(x.saturating_sub(pre_skip.into()) / 48)),
+        })
+    }
+
+    /// Decodes the next packet into the buffer, returning false once the stream has ended.
+    fn decode_packet(&mut self) -> bool {
+        loop {
+            let Ok(Some(packet)) = self.reader.read_packet() else {
+                return false;
+            };
+
+            if packet.stream_serial() != self.serial {
+                continue;
+            }
+
+            let channels = usize::from(self.channels);
+            self.buffer.resize(MAX_PACKET_SAMPLES * channels, 0);
+
+            let Ok(input) = (&packet.data).try_into() else {
+                continue;
+            };
+            let Ok(output) = MutSignals::try_from(&mut self.buffer) else {
+                return false;
+            };
+            let Ok(samples) = self.decoder.decode(Some(input), output, false) else {
+                continue;
+            };
+
+            self.buffer.truncate(samples * channels);
+            self.position = self.skip.min(self.buffer.len());
+            self.skip -= self.position;
+
+            if self.position < self.buffer.len() {
+                return true;
+            }
+        }
+    }
+}
+
+impl Iterator for OpusDecoder {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.buffer.len() && !self.decode_packet() {
+            return None;
+        }
+
+        let sample = self.buffer[self.position];
+        self.position += 1;
+
+        Some(sample)
+    }
+}
+
+impl Source for OpusDecoder {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.duration
+    }
+
+    fn try_seek(&mut self, _pos: Duration) -> Result<(), SeekError> {
+        Err(SeekError::NotSupported {
+            underlying_source: "OpusDecoder",
+        })
+    }
+}
@@ -0,0 +1,177 @@
+//! Contains the [`Quarantine`], which keeps tracks that are persistently
+//! broken from being picked over & over again.
+//!
+//! Tracks which the server says don't exist, or which fail to decode too many times,
+//! are saved in `quarantine` under [`data_dir`], one file per list. They stay there
+//! until they're cleared with `lowfi lists clear`.
+//!
+//! How many times each track has failed to decode is saved next to the quarantine,
+//! so that a broken track in a big list still gets quarantined across several sessions.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    sync::Mutex,
+};
+
+use tokio::fs;
+
+use crate::{data_dir, tracks::TrackError};
+
+/// How many times a track has to fail to decode before it's quarantined.
+const DECODE_FAILURES: usize = 3;
+
+/// The tracks of a list which have been quarantined.
+pub struct Quarantine {
+    /// Where the quarantined tracks are saved.
+    path: PathBuf,
+
+    /// Where the decode failures are saved.
+    failures_path: PathBuf,
+
+    /// The full paths of every quarantined track.
+    tracks: Mutex<HashSet<String>>,
+
+    /// How many times each track has failed to decode, which are saved as `{count} {full path}`.
+    failures: Mutex<HashMap<String, usize>>,
+}
+
+impl Quarantine {
+    /// Gets the path where the quarantine for a list called `name` is saved.
+    fn path(name: &str) -> eyre::Result<PathBuf> {
+        Ok(data_dir()?.join("quarantine").join(format!("{name}.txt")))
+    }
+
+    /// Gets the path where the decode failures of a list called `name` are saved.
+    fn failures_path(name: &str) -> eyre::Result<PathBuf> {
+        Ok(data_dir()?
+            .join("quarantine")
+            .join(format!("{name}.failures")))
+    }
+
+    /// Loads the quarantine for a list called `name`, which is empty if it hasn't been saved before.
+    pub async fn load(name: &str) -> eyre::Result<Self> {
+        let path = Self::path(name)?;
+        let tracks = if path.exists() {
+            fs::read_to_string(&path)
+                .await?
+                .lines()
+                .filter(|x| !x.is_empty())
+                .map(str::to_owned)
+                .collect()
+        } else {
+            HashSet::new()
+        };
+
+        let failures_path = Self::failures_path(name)?;
+        let failures = if failures_path.exists() {
+            fs::read_to_string(&failures_path)
+                .await?
+                .lines()
+                .filter_map(|x| {
+                    let (count, full_path) = x.split_once(' ')?;
+                    Some((full_path.to_owned(), count.parse().ok()?))
+                })
+                .collect()
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path,
+            failures_path,
+            tracks: Mutex::new(tracks),
+            failures: Mutex::new(failures),
+        })
+    }
+
+    /// Clears the quarantine of a list called `name`, along with its decode failures.
+    pub async fn clear(name: &str) -> eyre::Result<()> {
+        for path in [Self::path(name)?, Self::failures_path(name)?] {
+            if path.exists() {
+                fs::remove_file(path).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether a track has been quarantined.
+    pub fn contains(&self, full_path: &str) -> bool {
+        self.tracks.lock().unwrap().contains(full_path)
+    }
+
+    /// How many tracks have been quarantined.
+    pub fn len(&self) -> usize {
+        self.tracks.lock().unwrap().len()
+    }
+
+    /// Counts a decode failure of a track, returning how many times it's failed so far.
+    fn count_failure(&self, full_path: &str) -> usize {
+        let mut failures = self.failures.lock().unwrap();
+        let count = failures.entry(full_path.to_owned()).or_default();
+        *count += 1;
+        let count = *count;
+
+        // Quarantined tracks don't need to be counted anymore.
+        if count >= DECODE_FAILURES {
+            failures.remove(full_path);
+        }
+
+        count
+    }
+
+    /// Records an error from a track, quarantining it if the error
+    /// means that it's never going to work.
+    pub async fn report(&self, full_path: &str, error: &TrackError) {
+        let quarantine = if matches!(error, TrackError::Gone(_)) {
+            true
+        } else if error.is_decode() {
+            let count = self.count_failure(full_path);
+
+            // Failing to save just means that the track has a few more tries.
+            let _ = self.save_failures().await;
+
+            count >= DECODE_FAILURES
+        } else {
+            false
+        };
+
+        if quarantine && self.tracks.lock().unwrap().insert(full_path.to_owned()) {
+            // Failing to save just means that the track will be tried again next time.
+            let _ = self.save().await;
+        }
+    }
+
+    /// Saves how many times each track has failed to decode.
+    async fn save_failures(&self) -> eyre::Result<()> {
+        let mut contents: Vec<String> = self
+            .failures
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(full_path, count)| format!("{count} {full_path}"))
+            .collect();
+        contents.sort_unstable();
+
+        if let Some(parent) = self.failures_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&self.failures_path, contents.join("\n")).await?;
+
+        Ok(())
+    }
+
+    /// Saves the quarantined tracks.
+    async fn save(&self) -> eyre::Result<()> {
+        let mut contents: Vec<String> = self.tracks.lock().unwrap().iter().cloned().collect();
+        contents.sort_unstable();
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&self.path, contents.join("\n")).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,452 @@
+//! Contains a growing buffer which allows tracks to be decoded and
+//! played while they're still being downloaded.
+//!
+//! The downloading side holds a [`Writer`], which appends chunks as they
+//! arrive, while the decoder is given a [`Reader`]. Reads past the end of
+//! what has been downloaded so far will block until more data arrives,
+//! so streamed tracks are decoded ahead of time on their own thread by a [`Prefetch`],
+//! which plays silence if the download falls behind instead of stalling the audio thread.
+
+use std::{
+    io::{self, Read, Seek, SeekFrom},
+    mem,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, SyncSender, TryRecvError},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+    vec,
+};
+
+use bytes::Bytes;
+use rodio::{source::SeekError, Source};
+
+/// How many frames are decoded at once by a [`Prefetch`].
+const CHUNK_FRAMES: usize = 2048;
+
+/// How many chunks a [`Prefetch`] can decode ahead of what's being played,
+/// which is a bit over a second at 48kHz.
+const CHUNKS_AHEAD: usize = 32;
+
+/// The state of the download, as seen by the [`Reader`].
+#[derive(PartialEq, Eq, Clone, Copy)]
+enum Status {
+    /// More data is still on the way.
+    Downloading,
+
+    /// All of the data has been downloaded.
+    Finished,
+
+    /// The download failed or was abandoned, so no more data will arrive.
+    Failed,
+}
+
+/// The data shared between the [`Writer`] and [`Reader`].
+struct State {
+    /// Everything that has been downloaded so far.
+    data: Vec<u8>,
+
+    /// All of the data once the download has finished,
+    /// which is shared with whatever [`Writer::finish`] returned it to.
+    finished: Option<Bytes>,
+
+    /// The current state of the download.
+    status: Status,
+}
+
+impl State {
+    /// Everything that has been downloaded so far.
+    fn bytes(&self) -> &[u8] {
+        self.finished.as_deref().unwrap_or(&self.data)
+    }
+}
+
+/// The [`State`], along with the [`Condvar`] used to wake up a waiting [`Reader`].
+struct Shared {
+    /// The actual state.
+    state: Mutex<State>,
+
+    /// Notified whenever new data arrives, or when the download ends.
+    condvar: Condvar,
+}
+
+/// Creates a connected [`Writer`] & [`Reader`].
+///
+/// `length` is the expected length of the data, if it's known.
+pub fn channel(length: Option<u64>) -> (Writer, Reader) {
+    let capacity = length.and_then(|x| usize::try_from(x).ok()).unwrap_or(0);
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State {
+            data: Vec::with_capacity(capacity),
+            finished: None,
+            status: Status::Downloading,
+        }),
+        condvar: Condvar::new(),
+    });
+
+    (
+        Writer {
+            shared: Arc::clone(&shared),
+        },
+        Reader {
+            shared,
+            position: 0,
+            length,
+        },
+    )
+}
+
+/// The downloading half of a stream.
+///
+/// If this is dropped without calling [`Writer::finish`], then the
+/// download is treated as having failed.
+pub struct Writer {
+    /// The state shared with the [`Reader`].
+    shared: Arc<Shared>,
+}
+
+impl Writer {
+    /// Sets the status of the download, waking up the [`Reader`].
+    fn set_status(&self, status: Status) {
+        self.shared.state.lock().unwrap().status = status;
+        self.shared.condvar.notify_all();
+    }
+
+    /// Appends a newly downloaded chunk.
+    pub fn push(&self, chunk: &[u8]) {
+        self.shared
+            .state
+            .lock()
+            .unwrap()
+            .data
+            .extend_from_slice(chunk);
+        self.shared.condvar.notify_all();
+    }
+
+    /// Whether the [`Reader`] has been dropped, like when a track is skipped
+    /// before it finishes downloading, meaning there's no reason to continue.
+    pub fn abandoned(&self) -> bool {
+        Arc::strong_count(&self.shared) == 1
+    }
+
+    /// Marks the download as finished, returning all of the downloaded data.
+    pub fn finish(self) -> Bytes {
+        let mut state = self.shared.state.lock().unwrap();
+        let data = Bytes::from(mem::take(&mut state.data));
+        state.finished = Some(data.clone());
+        drop(state);
+
+        self.set_status(Status::Finished);
+        data
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.status == Status::Downloading {
+            state.status = Status::Failed;
+            drop(state);
+            self.shared.condvar.notify_all();
+        }
+    }
+}
+
+/// The reading half of a stream, which can be given to the decoder.
+pub struct Reader {
+    /// The state shared with the [`Writer`].
+    shared: Arc<Shared>,
+
+    /// The current position of the reader.
+    position: u64,
+
+    /// The expected length of the data, if it's known.
+    length: Option<u64>,
+}
+
+impl Reader {
+    /// Whether the download failed partway through, meaning that the track was cut off.
+    pub fn failed(&self) -> bool {
+        self.shared.state.lock().unwrap().status == Status::Failed
+    }
+
+    /// Creates another reader of the same stream, starting from the beginning.
+    pub fn reopen(&self) -> Self {
+        Self {
+            shared: Arc::clone(&self.shared),
+            position: 0,
+            length: self.length,
+        }
+    }
+}
+
+impl Read for Reader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let position = usize::try_from(self.position).map_err(io::Error::other)?;
+        let mut state = self.shared.state.lock().unwrap();
+
+        loop {
+            let data = state.bytes();
+            if position < data.len() {
+                let len = buf.len().min(data.len() - position);
+                buf[..len].copy_from_slice(&data[position..position + len]);
+                self.position += len as u64;
+
+                return Ok(len);
+            }
+
+            match state.status {
+                Status::Downloading => state = self.shared.condvar.wait(state).unwrap(),
+                Status::Finished => return Ok(0),
+                Status::Failed => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "track download failed",
+                    ))
+                }
+            }
+        }
+    }
+}
+
+impl Seek for Reader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                // Without a known length, the only option is to wait for the whole track.
+                let length = if let Some(length) = self.length {
+                    length
+                } else {
+                    let mut state = self.shared.state.lock().unwrap();
+                    while state.status == Status::Downloading {
+                        state = self.shared.condvar.wait(state).unwrap();
+                    }
+
+                    state.bytes().len() as u64
+                };
+
+                length.checked_add_signed(offset)
+            }
+        };
+
+        self.position = position
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek position"))?;
+
+        Ok(self.position)
+    }
+}
+
+/// A chunk of samples decoded by a [`Prefetch`].
+struct Chunk {
+    /// How many seeks had been requested before this chunk was decoded,
+    /// so that chunks from before the latest seek can be thrown away.
+    seeks: u64,
+
+    /// The samples, which are always a whole number of frames unless the track ended.
+    /// If this is empty, then the track has ended.
+    samples: Vec<i16>,
+}
+
+/// The latest seek requested from a [`Prefetch`], which is shared with its decoding thread.
+#[derive(Default)]
+struct SeekRequest {
+    /// How many seeks have been requested so far.
+    count: u64,
+
+    /// Where to seek to, if it hasn't been done yet.
+    position: Option<Duration>,
+}
+
+/// Decodes a streamed track on its own thread, ahead of what's being played.
+///
+/// The audio thread mixes every track which is playing, so it can't wait on
+/// a [`Reader`] without stalling a track that's being crossfaded out too.
+/// Instead, silence is played whenever the download falls behind.
+pub struct Prefetch {
+    /// The number of channels of the track.
+    channels: u16,
+
+    /// The sample rate of the track.
+    sample_rate: u32,
+
+    /// The duration of the track, if it's known.
+    total_duration: Option<Duration>,
+
+    /// Receives decoded chunks from the decoding thread.
+    chunks: Receiver<Chunk>,
+
+    /// The latest seek, along with how many there have been.
+    seek: Arc<Mutex<SeekRequest>>,
+
+    /// Wakes up the decoding thread after the track has ended, so it can seek back into it.
+    wake: SyncSender<()>,
+
+    /// How many seeks have been requested so far.
+    seeks: u64,
+
+    /// The rest of the chunk which is being played.
+    samples: vec::IntoIter<i16>,
+
+    /// How many samples of silence are left to finish the current frame of silence.
+    silence: u16,
+
+    /// Whether the decoding thread reached the end of the track.
+    ended: bool,
+
+    /// Whether the track can be seeked, which is set to false by the
+    /// decoding thread if it turns out that it can't.
+    seekable: Arc<AtomicBool>,
+}
+
+impl Prefetch {
+    /// Starts decoding `source` on a new thread.
+    ///
+    /// This seeks to the start of `source` to find out if it can be seeked at all, so it might
+    /// have to wait on the download & shouldn't be called directly in async code.
+    pub fn new<S: Source<Item = i16> + Send + 'static>(mut source: S) -> Self {
+        let channels = source.channels();
+        let sample_rate = source.sample_rate();
+        let total_duration = source.total_duration();
+
+        // Nothing has been decoded yet, so this doesn't change anything if it works.
+        let seekable = Arc::new(AtomicBool::new(source.try_seek(Duration::ZERO).is_ok()));
+
+        let (sender, chunks) = mpsc::sync_channel(CHUNKS_AHEAD);
+        let (wake, woken) = mpsc::sync_channel(1);
+        let seek = Arc::new(Mutex::new(SeekRequest::default()));
+
+        let shared = Arc::clone(&seek);
+        let works = Arc::clone(&seekable);
+        thread::spawn(move || {
+            let size = CHUNK_FRAMES * usize::from(channels.max(1));
+            let mut seeks = 0;
+
+            loop {
+                let samples: Vec<i16> = source.by_ref().take(size).collect();
+                let ended = samples.is_empty();
+
+                if sender.send(Chunk { seeks, samples }).is_err() {
+                    return;
+                }
+
+                // Once the track has ended, it can still be seeked back into, like when it repeats.
+                let position = loop {
+                    let mut seek = shared.lock().unwrap();
+                    if let Some(position) = seek.position.take() {
+                        seeks = seek.count;
+                        break Some(position);
+                    }
+
+                    drop(seek);
+                    if !ended {
+                        break None;
+                    }
+
+                    if woken.recv().is_err() {
+                        return;
+                    }
+                };
+
+                // Later seeks are refused if one fails, instead of pretending that they worked.
+                if let Some(position) = position {
+                    if source.try_seek(position).is_err() {
+                        works.store(false, Ordering::Relaxed);
+                    }
+                }
+            }
+        });
+
+        Self {
+            channels,
+            sample_rate,
+            total_duration,
+            chunks,
+            seek,
+            wake,
+            seeks: 0,
+            samples: Vec::new().into_iter(),
+            silence: 0,
+            ended: false,
+            seekable,
+        }
+    }
+}
+
+impl Iterator for Prefetch {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.silence > 0 {
+            self.silence -= 1;
+            return Some(0);
+        }
+
+        loop {
+            if let Some(sample) = self.samples.next() {
+                return Some(sample);
+            }
+
+            if self.ended {
+                return None;
+            }
+
+            match self.chunks.try_recv() {
+                // Chunks from before the latest seek are thrown away.
+                Ok(chunk) if chunk.seeks != self.seeks => {}
+                Ok(chunk) if chunk.samples.is_empty() => self.ended = true,
+                Ok(chunk) => self.samples = chunk.samples.into_iter(),
+                Err(TryRecvError::Empty) => {
+                    // Silence is played a whole frame at a time, so the channels stay in order.
+                    self.silence = self.channels.max(1) - 1;
+                    return Some(0);
+                }
+                Err(TryRecvError::Disconnected) => self.ended = true,
+            }
+        }
+    }
+}
+
+impl Source for Prefetch {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        if !self.seekable.load(Ordering::Relaxed) {
+            return Err(SeekError::NotSupported {
+                underlying_source: "Prefetch",
+            });
+        }
+
+        // The seek itself is done by the decoding thread, which could be waiting on the download.
+        let mut seek = self.seek.lock().unwrap();
+        seek.count += 1;
+        seek.position = Some(pos);
+        self.seeks = seek.count;
+        drop(seek);
+
+        let _ = self.wake.try_send(());
+
+        self.samples = Vec::new().into_iter();
+        self.silence = 0;
+        self.ended = false;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+//! Reads the artist, title & album of a track from the tags embedded
+//! in it, which are ID3 tags for MP3s and Vorbis comments for FLAC, Vorbis & Opus.
+
+use symphonia::core::{
+    formats::FormatOptions,
+    io::{MediaSourceStream, MediaSourceStreamOptions, ReadOnlySource},
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::Hint,
+};
+
+use super::TrackData;
+
+/// The tags of a track, any of which might be missing.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Tags {
+    /// The artist of the track.
+    pub artist: Option<String>,
+
+    /// The actual title of the track.
+    pub title: Option<String>,
+
+    /// The album which the track is from.
+    pub album: Option<String>,
+
+    /// The replay gain of the track in decibels, which is how much
+    /// its volume should be changed to match other tracks.
+    pub gain: Option<f32>,
+
+    /// The replay gain peak of the track, where one is full scale,
+    /// which limits how much louder it can be made without clipping.
+    pub peak: Option<f32>,
+}
+
+impl Tags {
+    /// Parses a replay gain value, which looks something like `-6.48 dB`.
+    pub fn parse_gain(value: &str) -> Option<f32> {
+        let value = value.trim();
+        let value = value
+            .strip_suffix("dB")
+            .or_else(|| value.strip_suffix("db"))
+            .unwrap_or(value);
+
+        value.trim().parse().ok().filter(|x: &f32| x.is_finite())
+    }
+
+    /// Fills in any tags which are missing with the ones from `other`.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            artist: self.artist.or(other.artist),
+            title: self.title.or(other.title),
+            album: self.album.or(other.album),
+            gain: self.gain.or(other.gain),
+            peak: self.peak.or(other.peak),
+        }
+    }
+
+    /// Fills in any tags which are still missing from a metadata revision.
+    fn merge(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            if tag.std_key == Some(StandardTagKey::ReplayGainTrackGain) && self.gain.is_none() {
+                self.gain = Self::parse_gain(&tag.value.to_string());
+                continue;
+            }
+
+            if tag.std_key == Some(StandardTagKey::ReplayGainTrackPeak) && self.peak.is_none() {
+                self.peak = tag
+                    .value
+                    .to_string()
+                    .trim()
+                    .parse()
+                    .ok()
+                    .filter(|x: &f32| x.is_finite());
+                continue;
+            }
+
+            let field = match tag.std_key {
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+
+            let value = tag.value.to_string();
+            let value = value.trim();
+            if field.is_none() && !value.is_empty() {
+                *field = Some(value.to_owned());
+            }
+        }
+    }
+
+    /// Reads the tags from the beginning of a track.
+    ///
+    /// This never fails, since tags are optional anyway, so if
+    /// anything goes wrong then the tags are just left empty.
+    pub fn read(data: TrackData) -> Self {
+        let mut tags = Self::default();
+
+        // The source is read only so that this never seeks to the end,
+        // which would mean waiting for a streamed track to be fully downloaded.
+        let source = Box::new(ReadOnlySource::new(data));
+        let stream = MediaSourceStream::new(source, MediaSourceStreamOptions::default());
+        let Ok(mut probed) = symphonia::default::get_probe().format(
+            &Hint::new(),
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        ) else {
+            return tags;
+        };
+
+        // Tags in the container itself, like Vorbis comments.
+        if let Some(revision) = probed.format.metadata().current() {
+            tags.merge(revision);
+        }
+
+        // Tags which come before the container, like ID3.
+        if let Some(metadata) = probed.metadata.get() {
+            if let Some(revision) = metadata.current() {
+                tags.merge(revision);
+            }
+        }
+
+        tags
+    }
+}